@@ -0,0 +1,147 @@
+//! This module provides the [`Aov`] type, which describes the extra passes that can be rendered
+//! alongside the image.
+
+use crate::{
+    random::{hash, hash_to_unit_float},
+    tonemap::ViewTransform,
+    vector::{v, Colour},
+};
+use image::{Rgb, Rgb32FImage, RgbImage};
+use serde::{Deserialize, Serialize};
+
+/// An arbitrary output variable, which is an extra pass rendered alongside the image, for
+/// compositing and denoising.
+///
+/// Saving a pass to a high dynamic range format keeps the raw values described below. Saving it
+/// to any other format scales the values so that they can be seen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, Deserialize, Serialize)]
+pub enum Aov {
+    /// The colour of the first surface that each sample hit, ignoring lighting, averaged over
+    /// the samples in each pixel.
+    Albedo,
+
+    /// The surface normal where each sample first hit something, facing the camera, averaged
+    /// over the samples in each pixel. Samples which don't hit anything count as zero.
+    Normal,
+
+    /// The distance from the camera to whatever the centre of each pixel shows, which is
+    /// infinite for pixels which don't show anything.
+    Depth,
+
+    /// The point in the world that the centre of each pixel shows, which is zero for pixels which
+    /// don't show anything.
+    Position,
+
+    /// One more than the index in the scene of the object that the centre of each pixel shows,
+    /// or zero for pixels which don't show an object.
+    ObjectId,
+
+    /// A number for the material that the centre of each pixel shows, or zero for pixels which
+    /// don't show anything. Materials get numbered from one in the order that they first appear,
+    /// going along each row of the image from the top.
+    MaterialId,
+
+    /// The light which came straight from a light, or from a light after a single bounce. This
+    /// gets filtered just like the image.
+    Direct,
+
+    /// The rest of the light, so that the direct and indirect passes add up to the image.
+    Indirect,
+
+    /// How many samples each pixel got.
+    SampleCount,
+}
+
+impl Aov {
+    /// The name of this pass, which goes in the names of the files that it gets saved to.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Albedo => "albedo",
+            Self::Normal => "normal",
+            Self::Depth => "depth",
+            Self::Position => "position",
+            Self::ObjectId => "object-id",
+            Self::MaterialId => "material-id",
+            Self::Direct => "direct",
+            Self::Indirect => "indirect",
+            Self::SampleCount => "sample-count",
+        }
+    }
+
+    /// Does this pass get collected from every sample while rendering? The other passes come from
+    /// a single ray through the centre of each pixel once the render is done, since averaging
+    /// things like depths or IDs doesn't make sense.
+    pub fn is_accumulated(self) -> bool {
+        matches!(
+            self,
+            Self::Albedo | Self::Normal | Self::Direct | Self::Indirect | Self::SampleCount
+        )
+    }
+
+    /// Turn the raw values of this pass into an image that can be looked at. The lighting passes
+    /// get the given view transform, and everything else gets scaled to fit.
+    pub fn to_display_image(self, img: &Rgb32FImage, view_transform: &ViewTransform) -> RgbImage {
+        let values = || img.pixels().map(|&pixel| Colour::from(pixel));
+        let map = |f: &dyn Fn(Colour) -> Colour| {
+            RgbImage::from_fn(img.width(), img.height(), |x, y| {
+                let colour = f(Colour::from(*img.get_pixel(x, y)));
+                Rgb(colour.map(|x| x.clamp(0., 1.)).into_u8_array())
+            })
+        };
+
+        match self {
+            Self::Direct | Self::Indirect => view_transform.apply_to_image(img),
+            Self::Albedo => ViewTransform::default().apply_to_image(img),
+            Self::Normal => map(&|normal| normal.normal_to_colour()),
+            Self::Depth => {
+                // Nearer things are brighter, and pixels which don't show anything are black
+                let (near, far) = values()
+                    .map(|depth| depth.x)
+                    .filter(|depth| depth.is_finite())
+                    .fold((f64::INFINITY, 0_f64), |(near, far), depth| {
+                        (near.min(depth), far.max(depth))
+                    });
+                let range = (far - near).max(f64::EPSILON);
+
+                map(&|depth| {
+                    if depth.x.is_finite() {
+                        v!(1. - 0.9 * (depth.x - near) / range)
+                    } else {
+                        Colour::ZERO
+                    }
+                })
+            }
+            Self::Position => {
+                let (min, max) = values().fold(
+                    (v!(f64::INFINITY), v!(f64::NEG_INFINITY)),
+                    |(min, max), position| {
+                        (min.min_elementwise(position), max.max_elementwise(position))
+                    },
+                );
+                let range = (max - min).map(|x| x.max(f64::EPSILON));
+
+                map(&|position| {
+                    let offset = position - min;
+                    v!(offset.x / range.x, offset.y / range.y, offset.z / range.z)
+                })
+            }
+            Self::ObjectId | Self::MaterialId => map(&|id| {
+                // Each ID gets a random colour, so that neighbouring IDs are easy to tell apart
+                if id.x <= 0. {
+                    Colour::ZERO
+                } else {
+                    let id = id.x as u64;
+                    v!(
+                        hash_to_unit_float(hash(&[id, 0])),
+                        hash_to_unit_float(hash(&[id, 1])),
+                        hash_to_unit_float(hash(&[id, 2]))
+                    )
+                }
+            }),
+            Self::SampleCount => {
+                let most_samples = values().map(|count| count.x).fold(1., f64::max);
+                map(&|count| count / most_samples)
+            }
+        }
+    }
+}
@@ -4,10 +4,7 @@ use crate::{
     ray::Ray,
     vector::{Point, Vec3},
 };
-use rand::{
-    distributions::{Distribution, Uniform},
-    thread_rng,
-};
+use core::f64::consts::{FRAC_PI_2, FRAC_PI_4};
 
 /// A simple camera.
 #[derive(Clone, Debug)]
@@ -110,8 +107,11 @@ impl Camera {
     /// The position of the pixel in each direction is given as a proportion of the total viewport
     /// size in that direction. The given proportions should be in the range `[0, 1]` and will be
     /// clamped down to that if they exceed it.
-    pub fn get_ray(&self, x_prop: f64, y_prop: f64) -> Ray {
-        let (rx, ry) = random_point_in_unit_circle();
+    ///
+    /// The lens sample is a point in the unit square which decides where on the lens the ray
+    /// starts from.
+    pub fn get_ray(&self, x_prop: f64, y_prop: f64, lens_sample: (f64, f64)) -> Ray {
+        let (rx, ry) = point_in_unit_circle(lens_sample);
         let origin =
             self.position + self.u * rx * self.lens_radius + self.v * ry * self.lens_radius;
 
@@ -122,18 +122,21 @@ impl Camera {
     }
 }
 
-/// Generate a random point in the unit circle.
-fn random_point_in_unit_circle() -> (f64, f64) {
-    let distribution = Uniform::new_inclusive(-1., 1.);
-    let mut rng = thread_rng();
-
-    let mut x = distribution.sample(&mut rng);
-    let mut y = distribution.sample(&mut rng);
-
-    while f64::sqrt(x * x + y * y) > 1. {
-        x = distribution.sample(&mut rng);
-        y = distribution.sample(&mut rng);
+/// Map a point in the unit square to a point in the unit circle, such that uniformly distributed
+/// points stay uniformly distributed.
+///
+/// This uses the concentric mapping from "A Low Distortion Map Between Disk and Square" by
+/// Shirley and Chiu, which keeps nearby points close together.
+fn point_in_unit_circle((u, v): (f64, f64)) -> (f64, f64) {
+    let (a, b) = (2. * u - 1., 2. * v - 1.);
+    if a == 0. && b == 0. {
+        return (0., 0.);
     }
 
-    (x, y)
+    let (radius, theta) = if a.abs() > b.abs() {
+        (a, FRAC_PI_4 * (b / a))
+    } else {
+        (b, FRAC_PI_2 - FRAC_PI_4 * (a / b))
+    };
+    (radius * theta.cos(), radius * theta.sin())
 }
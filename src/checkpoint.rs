@@ -0,0 +1,270 @@
+//! This module provides the [`Checkpoint`] type, which saves the progress of a render so that it
+//! can be resumed later.
+
+use crate::{film::Film, render::RenderOpts, RenderStats};
+use color_eyre::{
+    eyre::{ensure, Context},
+    Result,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// The bytes at the start of every checkpoint file, so that we can tell if we've been given the
+/// wrong file.
+const MAGIC: &[u8; 8] = b"RTCKPT\0\0";
+
+/// The version of the checkpoint format. This must be increased whenever anything saved in a
+/// checkpoint changes.
+const VERSION: u32 = 7;
+
+/// Everything needed to carry on with a render.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Checkpoint {
+    /// The command line arguments that the render was started with. The renderer doesn't use
+    /// these, but they let whoever resumes the render set up the same scene and camera.
+    pub arguments: Vec<String>,
+
+    /// The options that the render was using.
+    pub opts: RenderOpts,
+
+    /// How many passes of samples have been finished. When rendering tiles, this is the fewest
+    /// passes of any tile.
+    pub samples_done: u16,
+
+    /// The sample count given to the sampler for every sample. See
+    /// [`SamplerKind::create`](crate::sampler::SamplerKind::create).
+    ///
+    /// This is how many samples the render was first started with, even if the render has been
+    /// resumed with more samples since then or gets more samples from adaptive sampling, so that
+    /// adding samples doesn't change where the earlier ones went.
+    pub sampler_sample_count: u32,
+
+    /// How many passes of samples have been finished for each tile, in the order that the tiles
+    /// get rendered. This is empty when not rendering tiles.
+    pub tile_passes: Vec<u16>,
+
+    /// All the samples taken so far.
+    pub film: Film,
+
+    /// Statistics about all the paths traced so far.
+    pub stats: RenderStats,
+}
+
+impl Checkpoint {
+    /// Save this checkpoint to the given path.
+    ///
+    /// The checkpoint gets written to a temporary file first, and then moved over the old one,
+    /// so that the last checkpoint is still there if we get killed halfway through saving.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let mut temporary_path = path.as_os_str().to_owned();
+        temporary_path.push(".tmp");
+        let temporary_path = PathBuf::from(temporary_path);
+
+        let file = File::create(&temporary_path)
+            .wrap_err_with(|| format!("When trying to create {}", temporary_path.display()))?;
+        let mut writer = BufWriter::new(file);
+
+        writer
+            .write_all(MAGIC)
+            .and_then(|()| writer.write_all(&VERSION.to_le_bytes()))
+            .wrap_err_with(|| format!("When trying to write to {}", temporary_path.display()))?;
+        bincode::serialize_into(&mut writer, self)
+            .wrap_err_with(|| format!("When trying to write to {}", temporary_path.display()))?;
+        writer
+            .flush()
+            .wrap_err_with(|| format!("When trying to write to {}", temporary_path.display()))?;
+
+        fs::rename(&temporary_path, path).wrap_err_with(|| {
+            format!(
+                "When trying to move {} to {}",
+                temporary_path.display(),
+                path.display()
+            )
+        })
+    }
+
+    /// Load a checkpoint from the given path.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).wrap_err_with(|| format!("When trying to open {}", path.display()))?;
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0; 8];
+        let mut version = [0; 4];
+        reader
+            .read_exact(&mut magic)
+            .and_then(|()| reader.read_exact(&mut version))
+            .wrap_err_with(|| format!("When trying to read {}", path.display()))?;
+
+        ensure!(
+            &magic == MAGIC,
+            "{} is not a checkpoint file",
+            path.display()
+        );
+        let version = u32::from_le_bytes(version);
+        ensure!(
+            version == VERSION,
+            "{} is a version {version} checkpoint, but we can only read version {VERSION}",
+            path.display()
+        );
+
+        bincode::deserialize_from(reader)
+            .wrap_err_with(|| format!("When trying to read checkpoint from {}", path.display()))
+    }
+}
+
+/// Options for making checkpoints during a render.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckpointOpts {
+    /// How long to wait between checkpoints. A checkpoint can only be made between passes, so
+    /// this is really the shortest time between checkpoints.
+    pub interval: Duration,
+
+    /// The command line arguments to put in each checkpoint. See [`Checkpoint::arguments`].
+    pub arguments: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        camera::{Camera, CameraOpts},
+        object::{random_scene, Bvh},
+        render::Renderer,
+        vector::{Point, Vec3},
+    };
+    use image::Rgb32FImage;
+    use std::{
+        env,
+        sync::{Arc, Mutex},
+    };
+
+    /// A path in the temporary directory which no other test uses.
+    fn temporary_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("raytracer-{}-{name}.ckpt", std::process::id()))
+    }
+
+    fn opts(samples: u16, tile_size: Option<u32>) -> RenderOpts {
+        RenderOpts {
+            width: 24,
+            height: 16,
+            samples,
+            bounces: 8,
+            tile_size,
+            ..RenderOpts::default()
+        }
+    }
+
+    fn camera() -> Camera {
+        Camera::from(CameraOpts {
+            width: 24,
+            height: 16,
+            vertical_fov_degrees: 20.,
+            look_from: Point::new(13., 2., 3.),
+            look_at: Point::new(0., 0., 0.),
+            view_up: Vec3::new(0., 1., 0.),
+            aperture_width: 0.1,
+            focus_distance: 10.,
+        })
+    }
+
+    /// Render [`random_scene`] with the given renderer, and return the image along with the
+    /// final checkpoint.
+    fn render(renderer: Renderer) -> (Rgb32FImage, Checkpoint) {
+        let checkpoint = Arc::new(Mutex::new(None));
+        let renderer = {
+            let checkpoint = Arc::clone(&checkpoint);
+            renderer.with_checkpoints(
+                CheckpointOpts {
+                    interval: Duration::MAX,
+                    arguments: vec!["raytracer".to_string(), "--samples=2".to_string()],
+                },
+                move |new| *checkpoint.lock().unwrap() = Some(new.clone()),
+            )
+        };
+        let image = renderer.render(&Bvh::new(random_scene(0)), &camera());
+        let checkpoint = checkpoint.lock().unwrap().take();
+        (
+            image,
+            checkpoint.expect("the render should make a final checkpoint"),
+        )
+    }
+
+    fn bits(image: &Rgb32FImage) -> Vec<u32> {
+        image.as_raw().iter().map(|value| value.to_bits()).collect()
+    }
+
+    #[test]
+    fn a_saved_checkpoint_loads_the_same() {
+        let (_, checkpoint) = render(Renderer::new(opts(2, None)));
+        let path = temporary_path("round-trip");
+        checkpoint.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            bincode::serialize(&loaded.unwrap()).unwrap(),
+            bincode::serialize(&checkpoint).unwrap()
+        );
+    }
+
+    #[test]
+    fn files_with_the_wrong_magic_bytes_are_rejected() {
+        let path = temporary_path("wrong-magic");
+        let mut contents = b"NOTACKPT".to_vec();
+        contents.extend(VERSION.to_le_bytes());
+        contents.extend([0; 64]);
+        fs::write(&path, contents).unwrap();
+        let error = Checkpoint::load(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+
+        assert!(
+            error.to_string().contains("is not a checkpoint file"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn checkpoints_from_other_versions_are_rejected() {
+        let (_, checkpoint) = render(Renderer::new(opts(1, None)));
+        let path = temporary_path("wrong-version");
+        checkpoint.save(&path).unwrap();
+        let mut contents = fs::read(&path).unwrap();
+        contents[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(VERSION - 1).to_le_bytes());
+        fs::write(&path, contents).unwrap();
+        let error = Checkpoint::load(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+
+        assert!(
+            error
+                .to_string()
+                .contains(&format!("is a version {} checkpoint", VERSION - 1)),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn resuming_gives_the_same_image_as_rendering_straight_through() {
+        for tile_size in [None, Some(8)] {
+            let (straight, _) = render(Renderer::new(opts(4, tile_size)));
+
+            let (_, checkpoint) = render(Renderer::new(opts(2, tile_size)));
+            assert_eq!(checkpoint.samples_done, 2);
+            let (resumed, checkpoint) = render(
+                Renderer::new(opts(4, tile_size))
+                    .resume_from(checkpoint)
+                    .unwrap(),
+            );
+            assert_eq!(checkpoint.samples_done, 4);
+
+            assert_eq!(bits(&resumed), bits(&straight), "with tiles {tile_size:?}");
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! This module provides a denoiser, which smooths out the noise in an image rendered with only a
+//! few samples.
+
+use crate::{
+    aov::Aov,
+    film::Film,
+    vector::{v, Colour, Vec3},
+};
+use image::Rgb32FImage;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+
+/// The weights of the B3 spline, which is the kernel of the à-trous wavelet transform in each
+/// dimension.
+const KERNEL: [f64; 5] = [1. / 16., 1. / 4., 3. / 8., 1. / 4., 1. / 16.];
+
+/// Albedos darker than this don't get divided out of the image, since dividing by them would
+/// blow up the noise. This mostly happens for lights and the sky.
+const MIN_ALBEDO: f64 = 0.01;
+
+/// Options for the denoiser.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct DenoiseOpts {
+    /// How many passes of the filter to do. Each pass reaches twice as far as the last one, so
+    /// five passes reach 31 pixels away in each direction.
+    pub iterations: u32,
+
+    /// How different in brightness two pixels can be, in standard errors of their noise, before
+    /// they stop getting blended together. Bigger values give a smoother image, but blur more
+    /// detail.
+    pub strength: f64,
+
+    /// How sharply the weight falls off as the normals of two pixels get further apart. Bigger
+    /// values keep edges between surfaces crisper.
+    pub normal_sharpness: f64,
+
+    /// How different the albedos of two pixels can be before they stop getting blended together.
+    pub albedo_sigma: f64,
+}
+
+impl Default for DenoiseOpts {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            strength: 4.,
+            normal_sharpness: 64.,
+            albedo_sigma: 0.1,
+        }
+    }
+}
+
+/// Everything known about one pixel while denoising.
+#[derive(Clone, Copy, Debug)]
+struct Pixel {
+    /// The colour of the pixel divided by its albedo, which is roughly the light arriving at it.
+    irradiance: Colour,
+
+    /// The variance of the luminance of the irradiance.
+    variance: f64,
+
+    /// The albedo of the pixel, or white if we don't know it.
+    albedo: Colour,
+
+    /// The normal of the pixel, or zero if we don't know it.
+    normal: Vec3,
+}
+
+/// Denoise the image in the given film with an edge-avoiding à-trous wavelet filter, from
+/// "Edge-Avoiding À-Trous Wavelet Transform for fast Global Illumination Filtering" by Dammertz
+/// et al., with the variance guidance from "Spatiotemporal Variance-Guided Filtering" by
+/// Schied et al.
+///
+/// Each pass blurs the image with a sparse kernel, where the weight of each neighbour falls off
+/// as its brightness, normal and albedo get further from the pixel's own. The brightness is
+/// compared against how noisy the pixel is, so noisy areas get smoothed a lot while converged
+/// areas keep their detail. The albedo is divided out of the image before filtering and put back
+/// afterwards, so textures stay sharp.
+///
+/// The film should collect the albedo and normal passes. The denoiser still works without them,
+/// but it blurs across edges much more.
+pub fn denoise(film: &Film, opts: &DenoiseOpts) -> Rgb32FImage {
+    let region = film.region();
+    let colour = film.to_image();
+    let albedo = film.aov_image(Aov::Albedo);
+    let normal = film.aov_image(Aov::Normal);
+
+    let mut pixels: Vec<Pixel> = (0..region.pixel_count())
+        .into_par_iter()
+        .map(|index| {
+            let (x, y) = region.pixel(index);
+            let get = |img: &Rgb32FImage| Colour::from(*img.get_pixel(x, y));
+
+            let colour = get(&colour);
+            let albedo = albedo.as_ref().map_or(v!(1), get);
+            let normal = normal.as_ref().map_or(Vec3::ZERO, get);
+            let modulation = modulation(albedo);
+
+            Pixel {
+                irradiance: v!(
+                    colour.x / modulation.x,
+                    colour.y / modulation.y,
+                    colour.z / modulation.z
+                ),
+                variance: film.statistics(x, y).variance_of_mean() / modulation.luminance().powi(2),
+                albedo,
+                normal: if normal.is_zero() {
+                    normal
+                } else {
+                    normal.normalise()
+                },
+            }
+        })
+        .collect();
+
+    // The variance of a single pixel is very noisy itself, so we blur it a bit first
+    let blurred_variance: Vec<f64> = (0..region.pixel_count())
+        .into_par_iter()
+        .map(|index| {
+            let (x, y) = region.pixel(index);
+            let (mut sum, mut weight_sum) = (0., 0.);
+            for j in y.saturating_sub(1)..(y + 2).min(region.bottom()) {
+                for i in x.saturating_sub(1)..(x + 2).min(region.right()) {
+                    if region.contains(i, j) {
+                        let weight = KERNEL[(i + 2 - x) as usize] * KERNEL[(j + 2 - y) as usize];
+                        sum += weight * pixels[region.index(i, j)].variance;
+                        weight_sum += weight;
+                    }
+                }
+            }
+            sum / weight_sum
+        })
+        .collect();
+    for (pixel, variance) in pixels.iter_mut().zip(blurred_variance) {
+        pixel.variance = variance;
+    }
+
+    for iteration in 0..opts.iterations {
+        let step = 1_i64 << iteration;
+        pixels = (0..region.pixel_count())
+            .into_par_iter()
+            .map(|index| {
+                let (x, y) = region.pixel(index);
+                let centre = pixels[index];
+                let brightness_scale = opts.strength * centre.variance.sqrt() + f64::MIN_POSITIVE;
+
+                let mut irradiance = Colour::ZERO;
+                let mut variance = 0.;
+                let mut weight_sum = 0.;
+
+                for (dy, kernel_y) in (-2..=2).zip(KERNEL) {
+                    for (dx, kernel_x) in (-2..=2).zip(KERNEL) {
+                        let (i, j) = (x as i64 + dx * step, y as i64 + dy * step);
+                        if i < 0 || j < 0 || !region.contains(i as u32, j as u32) {
+                            continue;
+                        }
+                        let other = pixels[region.index(i as u32, j as u32)];
+
+                        let brightness_weight =
+                            (-(centre.irradiance.luminance() - other.irradiance.luminance()).abs()
+                                / brightness_scale)
+                                .exp();
+                        let normal_weight = if centre.normal.is_zero() && other.normal.is_zero() {
+                            1.
+                        } else {
+                            centre
+                                .normal
+                                .dot(other.normal)
+                                .max(0.)
+                                .powf(opts.normal_sharpness)
+                        };
+                        let albedo_difference = centre.albedo - other.albedo;
+                        let albedo_weight = (-albedo_difference.dot(albedo_difference)
+                            / (opts.albedo_sigma * opts.albedo_sigma))
+                            .exp();
+
+                        let weight =
+                            kernel_x * kernel_y * brightness_weight * normal_weight * albedo_weight;
+
+                        // Pixels with fewer than two samples have an infinite variance, which
+                        // would turn into NaN if we multiplied it by zero
+                        if weight == 0. {
+                            continue;
+                        }
+                        irradiance += other.irradiance * weight;
+                        variance += other.variance * weight * weight;
+                        weight_sum += weight;
+                    }
+                }
+
+                // The pixel itself always has a positive weight, so this never divides by zero
+                Pixel {
+                    irradiance: irradiance / weight_sum,
+                    variance: variance / (weight_sum * weight_sum),
+                    ..centre
+                }
+            })
+            .collect();
+    }
+
+    let mut img = Rgb32FImage::new(film.width(), film.height());
+    for (index, pixel) in pixels.iter().enumerate() {
+        let (x, y) = region.pixel(index);
+        img.put_pixel(
+            x,
+            y,
+            pixel
+                .irradiance
+                .mul_elementwise(modulation(pixel.albedo))
+                .into(),
+        );
+    }
+
+    img
+}
+
+/// What to divide the colour of a pixel with the given albedo by, so that we only filter the
+/// light arriving at it.
+fn modulation(albedo: Colour) -> Colour {
+    albedo.map(|x| if x > MIN_ALBEDO { x } else { 1. })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{film::FilmSample, filter::Filter, tile::Region};
+
+    const WIDTH: u32 = 16;
+    const HEIGHT: u32 = 8;
+
+    /// Make a film where each pixel gets a few grey samples with the given brightness and
+    /// normal. Every other sample is brighter or darker by the given proportion, so that the
+    /// pixels look noisy, but the mean of each pixel is still its brightness.
+    fn film(pixel: impl Fn(u32, u32) -> (f64, Vec3), noise: f64) -> Film {
+        let region = Region::full(WIDTH, HEIGHT);
+        let mut film = Film::new(WIDTH, HEIGHT, region, Filter::default())
+            .with_aovs(&[Aov::Albedo, Aov::Normal]);
+
+        for pass in 0..4 {
+            let offset = if pass % 2 == 0 { noise } else { -noise };
+            let samples: Vec<_> = (0..region.pixel_count())
+                .map(|index| {
+                    let (x, y) = region.pixel(index);
+                    let (brightness, normal) = pixel(x, y);
+                    Some(FilmSample {
+                        position: (x as f64 + 0.5, y as f64 + 0.5),
+                        colour: v!(brightness * (1. + offset)),
+                        direct: Colour::ZERO,
+                        albedo: v!(0.5),
+                        normal,
+                    })
+                })
+                .collect();
+            film.add_pass(region, &samples);
+        }
+
+        film
+    }
+
+    /// The brightness of the given pixel of a grey image.
+    fn brightness(image: &Rgb32FImage, x: u32, y: u32) -> f64 {
+        image.get_pixel(x, y).0[0] as f64
+    }
+
+    #[test]
+    fn a_constant_image_is_unchanged() {
+        let image = denoise(
+            &film(|_, _| (0.3, v!(0, 0, 1)), 0.),
+            &DenoiseOpts::default(),
+        );
+
+        for (x, y, pixel) in image.enumerate_pixels() {
+            for value in pixel.0 {
+                assert!(
+                    (value as f64 - 0.3).abs() < 1e-6,
+                    "pixel ({x}, {y}) changed to {value}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn edges_between_normals_are_not_blurred_across() {
+        // The left half of the image is a dark surface facing the camera, and the right half is
+        // a bright surface at right angles to it
+        let side = |x| {
+            if x < WIDTH / 2 {
+                (0.2, v!(0, 0, 1))
+            } else {
+                (0.8, v!(1, 0, 0))
+            }
+        };
+        let opts = DenoiseOpts::default();
+        let with_normals = denoise(&film(|x, _| side(x), 0.5), &opts);
+        let without_normals = denoise(&film(|x, _| (side(x).0, v!(0, 0, 1)), 0.5), &opts);
+
+        for (x, y, _) in with_normals.enumerate_pixels() {
+            let expected = side(x).0;
+            let actual = brightness(&with_normals, x, y);
+            assert!(
+                (actual - expected).abs() < 1e-6,
+                "pixel ({x}, {y}) is {actual} rather than {expected}"
+            );
+        }
+
+        // Make sure that it's the normals which keep the edge sharp
+        let edge = WIDTH / 2 - 1;
+        assert!(brightness(&without_normals, edge, 0) > 0.25);
+    }
+}
@@ -0,0 +1,42 @@
+//! This module provides the [`Gradient`] environment.
+
+use super::Environment;
+use crate::vector::{v, Colour, Vec3};
+
+/// An environment which blends from one colour straight down to another colour straight up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gradient {
+    /// The colour looking straight down.
+    pub bottom: Colour,
+
+    /// The colour looking straight up.
+    pub top: Colour,
+}
+
+impl Gradient {
+    /// Create a new gradient.
+    pub fn new(bottom: Colour, top: Colour) -> Self {
+        Self { bottom, top }
+    }
+}
+
+/// The default gradient is a simple blue sky.
+impl Default for Gradient {
+    fn default() -> Self {
+        Self {
+            bottom: v!(1),
+            top: v!(0.5, 0.7, 1),
+        }
+    }
+}
+
+impl Environment for Gradient {
+    fn colour(&self, direction: Vec3) -> Colour {
+        let height = 0.5 * (direction.normalise().y + 1.);
+        debug_assert!(
+            (0.0..=1.0).contains(&height),
+            "The height must be in [0, 1]: {height}"
+        );
+        (1. - height) * self.bottom + height * self.top
+    }
+}
@@ -0,0 +1,222 @@
+//! This module provides the [`EnvironmentMap`] type.
+
+use super::Environment;
+use crate::{
+    sampler::Sampler,
+    texture::ImageTexture,
+    vector::{Colour, Vec3},
+};
+use color_eyre::{eyre::Context, Result};
+use core::f64::consts::PI;
+use image::Rgb32FImage;
+use std::path::Path;
+
+/// An environment which comes from an equirectangular (latitude-longitude) image, usually a high
+/// dynamic range photo of a real place.
+///
+/// The top row of the image is straight up, the bottom row is straight down, and the middle of
+/// the image is along -Z before rotating.
+///
+/// Bright parts of the image get importance sampled, so small bright light sources like the sun
+/// light the scene without much noise.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnvironmentMap {
+    /// The image, in linear colour space.
+    image: Rgb32FImage,
+
+    /// How far the image is rotated anticlockwise around the Y axis, in radians.
+    rotation: f64,
+
+    /// How much to scale the brightness of the image by.
+    intensity: f64,
+
+    /// The sampling weight of each pixel, in row-major order. This is the luminance of the pixel
+    /// times the area that it covers on the sphere.
+    weights: Vec<f64>,
+
+    /// The sum of all the weights.
+    total_weight: f64,
+
+    /// The cumulative distribution for picking a row, normalised to end at 1.
+    row_cdf: Vec<f64>,
+
+    /// The cumulative distribution for picking a pixel within each row, normalised to end at 1.
+    column_cdfs: Vec<Vec<f64>>,
+}
+
+impl EnvironmentMap {
+    /// Create a new environment map from an image which is already in linear colour space.
+    ///
+    /// The rotation is anticlockwise around the Y axis, in radians.
+    pub fn new(image: Rgb32FImage, rotation: f64, intensity: f64) -> Self {
+        let (width, height) = image.dimensions();
+
+        let weights: Vec<f64> = image
+            .enumerate_pixels()
+            .map(|(_, y, pixel)| {
+                // Rows near the poles cover less of the sphere
+                let theta = (y as f64 + 0.5) / height as f64 * PI;
+                Colour::from(*pixel).luminance().max(0.) * theta.sin()
+            })
+            .collect();
+
+        let mut row_totals = Vec::with_capacity(height as usize);
+        let mut column_cdfs = Vec::with_capacity(height as usize);
+        if width > 0 {
+            for row in weights.chunks_exact(width as usize) {
+                let (cdf, total) = cumulative_distribution(row.iter().copied());
+                row_totals.push(total);
+                column_cdfs.push(cdf);
+            }
+        }
+        let (row_cdf, total_weight) = cumulative_distribution(row_totals.into_iter());
+
+        Self {
+            image,
+            rotation,
+            intensity,
+            weights,
+            total_weight,
+            row_cdf,
+            column_cdfs,
+        }
+    }
+
+    /// Load an environment map from a file.
+    ///
+    /// Float images like EXR and HDR files are assumed to be linear already, and every other
+    /// image is assumed to be sRGB encoded. The rotation is anticlockwise around the Y axis, in
+    /// radians.
+    pub fn load(path: impl AsRef<Path>, rotation: f64, intensity: f64) -> Result<Self> {
+        let path = path.as_ref();
+        let image = ImageTexture::load(path)
+            .wrap_err_with(|| format!("When trying to load environment map {}", path.display()))?
+            .into_image();
+        Ok(Self::new(image, rotation, intensity))
+    }
+
+    /// Get the UV coordinates in the image of the given direction, along with the sine of the
+    /// angle between the direction and the Y axis.
+    fn direction_to_uv(&self, direction: Vec3) -> ((f64, f64), f64) {
+        let local = rotate_y(direction.normalise(), -self.rotation);
+
+        let cos_theta = local.y.clamp(-1., 1.);
+        let phi = f64::atan2(local.x, -local.z);
+        let u = 0.5 + phi / (2. * PI);
+        let v = cos_theta.acos() / PI;
+
+        ((u, v), (1. - cos_theta * cos_theta).sqrt())
+    }
+
+    /// Get the direction pointing at the given UV coordinates in the image.
+    fn uv_to_direction(&self, (u, v): (f64, f64)) -> Vec3 {
+        let theta = v * PI;
+        let phi = (u - 0.5) * 2. * PI;
+        let local = Vec3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        );
+        rotate_y(local, self.rotation)
+    }
+
+    /// Get the coordinates of the pixel containing the given UV coordinates.
+    fn pixel_at(&self, (u, v): (f64, f64)) -> (u32, u32) {
+        let (width, height) = self.image.dimensions();
+        let x = (u.rem_euclid(1.) * width as f64) as u32;
+        let y = (v.clamp(0., 1.) * height as f64) as u32;
+        (x.min(width - 1), y.min(height - 1))
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn colour(&self, direction: Vec3) -> Colour {
+        let (width, height) = self.image.dimensions();
+        if width == 0 || height == 0 {
+            return Colour::ZERO;
+        }
+
+        let (uv, _) = self.direction_to_uv(direction);
+        let (x, y) = self.pixel_at(uv);
+        self.intensity * Colour::from(*self.image.get_pixel(x, y))
+    }
+
+    fn supports_sampling(&self) -> bool {
+        self.total_weight > 0.
+    }
+
+    fn sample_direction(&self, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        if !self.supports_sampling() {
+            return None;
+        }
+
+        let (width, height) = self.image.dimensions();
+        let (row_sample, column_sample) = sampler.next_2d();
+        let y = sample_cdf(&self.row_cdf, row_sample);
+        let x = sample_cdf(&self.column_cdfs[y], column_sample);
+
+        let (jitter_x, jitter_y) = sampler.next_2d();
+        let u = (x as f64 + jitter_x) / width as f64;
+        let v = (y as f64 + jitter_y) / height as f64;
+        Some(self.uv_to_direction((u, v)))
+    }
+
+    fn direction_pdf(&self, direction: Vec3) -> f64 {
+        if !self.supports_sampling() {
+            return 0.;
+        }
+
+        let (width, height) = self.image.dimensions();
+        let (uv, sin_theta) = self.direction_to_uv(direction);
+        if sin_theta <= 0. {
+            return 0.;
+        }
+
+        // Pixels are picked in proportion to their weight, and then points are picked uniformly
+        // within each pixel
+        let (x, y) = self.pixel_at(uv);
+        let uv_pdf = self.weights[(y * width + x) as usize] / self.total_weight
+            * width as f64
+            * height as f64;
+
+        // The area of the sphere covered by a small patch of the image shrinks towards the poles
+        uv_pdf / (2. * PI * PI * sin_theta)
+    }
+}
+
+/// Rotate a vector anticlockwise around the Y axis by the given angle in radians.
+fn rotate_y(vector: Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    Vec3::new(
+        vector.x * cos + vector.z * sin,
+        vector.y,
+        -vector.x * sin + vector.z * cos,
+    )
+}
+
+/// Build a cumulative distribution from some weights. This returns the distribution, normalised
+/// to end at 1, and the total weight.
+fn cumulative_distribution(weights: impl Iterator<Item = f64>) -> (Vec<f64>, f64) {
+    let mut cdf: Vec<f64> = weights
+        .scan(0., |total, weight| {
+            *total += weight;
+            Some(*total)
+        })
+        .collect();
+
+    let total = cdf.last().copied().unwrap_or(0.);
+    if total > 0. {
+        for value in &mut cdf {
+            *value /= total;
+        }
+    }
+
+    (cdf, total)
+}
+
+/// Pick an index from a cumulative distribution, given a uniform random number in `[0, 1)`.
+fn sample_cdf(cdf: &[f64], random: f64) -> usize {
+    // Skip past any entries with zero weight, which have the same value as the previous entry
+    cdf.partition_point(|&value| value <= random)
+        .min(cdf.len() - 1)
+}
@@ -0,0 +1,74 @@
+//! This module deals with environments, which decide how much light arrives from infinitely far
+//! away along rays which don't hit anything.
+
+mod gradient;
+mod map;
+
+use crate::{
+    sampler::Sampler,
+    vector::{Colour, Vec3},
+};
+use std::sync::Arc;
+
+pub use self::{gradient::Gradient, map::EnvironmentMap};
+
+/// A trait to represent the environment surrounding a scene.
+///
+/// Environments must be [`Send`] and [`Sync`] so that the scene can be rendered on several
+/// threads.
+pub trait Environment: Send + Sync {
+    /// The light arriving from the given direction. The direction might not be normalised.
+    fn colour(&self, direction: Vec3) -> Colour;
+
+    /// Can this environment be sampled as a light with [`Environment::sample_direction`]?
+    ///
+    /// This should only be true for environments which have bright spots that are hard to find
+    /// by chance. Smooth environments are sampled well enough by the materials.
+    fn supports_sampling(&self) -> bool {
+        false
+    }
+
+    /// Pick a random direction, preferring directions where more light arrives from.
+    fn sample_direction(&self, _sampler: &mut dyn Sampler) -> Option<Vec3> {
+        None
+    }
+
+    /// The probability density, with respect to solid angle, of
+    /// [`Environment::sample_direction`] picking the given direction.
+    fn direction_pdf(&self, _direction: Vec3) -> f64 {
+        0.
+    }
+}
+
+/// A plain colour is an environment where the same light arrives from every direction. Use
+/// [`Colour::ZERO`] for a black environment.
+impl Environment for Colour {
+    fn colour(&self, _direction: Vec3) -> Colour {
+        *self
+    }
+}
+
+// This lets us choose environments at runtime with `Arc<dyn Environment>`.
+impl<E> Environment for Arc<E>
+where
+    E: Environment + ?Sized,
+{
+    fn colour(&self, direction: Vec3) -> Colour {
+        (**self).colour(direction)
+    }
+
+    fn supports_sampling(&self) -> bool {
+        (**self).supports_sampling()
+    }
+
+    fn sample_direction(&self, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        (**self).sample_direction(sampler)
+    }
+
+    fn direction_pdf(&self, direction: Vec3) -> f64 {
+        (**self).direction_pdf(direction)
+    }
+}
+
+/// An environment which can be chosen at runtime.
+pub type SharedEnvironment = Arc<dyn Environment>;
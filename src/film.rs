@@ -0,0 +1,507 @@
+//! This module provides the [`Film`] type, which accumulates samples into an image.
+
+use crate::{
+    aov::Aov,
+    filter::Filter,
+    tile::Region,
+    vector::{v, Colour, Vec3},
+};
+use image::{GrayImage, Luma, Rgb32FImage};
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
+use rayon::slice::ParallelSliceMut;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// A single sample of the image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FilmSample {
+    /// Where the sample was taken, in pixels from the top left corner of the image. The centre of
+    /// pixel `(i, j)` is at `(i + 0.5, j + 0.5)`.
+    pub position: (f64, f64),
+
+    /// The colour of the sample.
+    pub colour: Colour,
+
+    /// The part of the colour which is direct light. See [`TracedPath`](crate::ray::TracedPath).
+    pub direct: Colour,
+
+    /// The albedo of the first surface that the sample hit.
+    pub albedo: Colour,
+
+    /// The surface normal where the sample first hit something.
+    pub normal: Vec3,
+}
+
+/// The smallest luminance that we divide by when working out the relative error of a pixel, so
+/// that nearly black pixels don't need an absurd number of samples to converge.
+const MIN_ERROR_LUMINANCE: f64 = 0.01;
+
+/// How many entries the lookup table of the filter has for each pixel of its radius.
+const FILTER_TABLE_RESOLUTION: f64 = 256.;
+
+/// Statistics about the samples taken within a single pixel, which tell us how noisy it is.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct PixelStatistics {
+    /// How many samples have been taken within the pixel.
+    pub count: u32,
+
+    /// The sum of the luminances of the samples.
+    pub luminance_sum: f64,
+
+    /// The sum of the squares of the luminances of the samples.
+    pub luminance_square_sum: f64,
+}
+
+impl PixelStatistics {
+    /// Record a sample with the given colour.
+    fn record(&mut self, colour: Colour) {
+        let luminance = colour.luminance();
+        self.count += 1;
+        self.luminance_sum += luminance;
+        self.luminance_square_sum += luminance * luminance;
+    }
+
+    /// Combine the statistics from two sets of samples in the same pixel.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            count: self.count + other.count,
+            luminance_sum: self.luminance_sum + other.luminance_sum,
+            luminance_square_sum: self.luminance_square_sum + other.luminance_square_sum,
+        }
+    }
+
+    /// The mean luminance of the samples, or zero if there aren't any.
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            0.
+        } else {
+            self.luminance_sum / self.count as f64
+        }
+    }
+
+    /// Estimate the variance of the mean luminance of the samples, which is the square of its
+    /// standard error.
+    ///
+    /// This is infinite if the pixel has fewer than two samples, since we can't tell how noisy
+    /// it is yet.
+    pub fn variance_of_mean(&self) -> f64 {
+        let count = self.count as f64;
+        if count < 2. {
+            return f64::INFINITY;
+        }
+
+        let mean = self.mean();
+        let variance =
+            (self.luminance_square_sum / count - mean * mean).max(0.) * count / (count - 1.);
+
+        variance / count
+    }
+
+    /// Estimate the relative error of the pixel, which is the standard error of the mean
+    /// luminance of its samples divided by that mean.
+    ///
+    /// This is infinite if the pixel has fewer than two samples, since we can't tell how noisy
+    /// it is yet.
+    pub fn relative_error(&self) -> f64 {
+        self.variance_of_mean().sqrt() / self.mean().max(MIN_ERROR_LUMINANCE)
+    }
+}
+
+/// An image which samples get splatted onto with a reconstruction [`Filter`].
+///
+/// This keeps the weighted sum of the samples and the sum of the weights for every pixel, so
+/// that the image is always the weighted average of all the samples so far. It also keeps track
+/// of the samples taken within each pixel, so that we can estimate how noisy each pixel is.
+///
+/// A film can cover just one region of the image, in which case it ignores any samples splatted
+/// outside that region, and the rest of the image stays black.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Film {
+    /// The width of the whole image.
+    width: u32,
+
+    /// The height of the whole image.
+    height: u32,
+
+    /// The region of the image covered by this film.
+    region: Region,
+
+    /// The filter used to weight the samples.
+    filter: Filter,
+
+    /// The filter evaluated at evenly spaced distances from zero to its radius, because
+    /// evaluating some filters directly for every sample and pixel would be too slow.
+    ///
+    /// This can always be rebuilt from the filter, so it doesn't get saved. A film which has
+    /// just been loaded has an empty table until it's needed.
+    #[serde(skip)]
+    filter_table: Arc<[f64]>,
+
+    /// The weighted sum of the colours of the samples for each pixel in the region, in
+    /// row-major order.
+    colour_sums: Vec<Colour>,
+
+    /// The sum of the weights of the samples for each pixel in the region, in row-major order.
+    weight_sums: Vec<f64>,
+
+    /// Statistics about the samples taken within each pixel in the region, in row-major order.
+    statistics: Vec<PixelStatistics>,
+
+    /// The weighted sum of the direct light of the samples for each pixel in the region, in
+    /// row-major order. This is empty unless we're collecting the direct or indirect pass.
+    direct_sums: Vec<Colour>,
+
+    /// The sum of the albedos of the samples taken within each pixel in the region, in
+    /// row-major order. This is empty unless we're collecting the albedo pass.
+    albedo_sums: Vec<Colour>,
+
+    /// The sum of the normals of the samples taken within each pixel in the region, in
+    /// row-major order. This is empty unless we're collecting the normal pass.
+    normal_sums: Vec<Vec3>,
+}
+
+impl Film {
+    /// Create an empty film for an image with the given size, which covers the given region of
+    /// the image and uses the given filter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the region is not inside the image.
+    pub fn new(width: u32, height: u32, region: Region, filter: Filter) -> Self {
+        assert!(
+            region.right() <= width && region.bottom() <= height,
+            "The region of the film must be inside the image"
+        );
+
+        let pixel_count = region.pixel_count();
+        Self {
+            width,
+            height,
+            region,
+            filter_table: filter_table(&filter),
+            filter,
+            colour_sums: vec![Colour::ZERO; pixel_count],
+            weight_sums: vec![0.; pixel_count],
+            statistics: vec![PixelStatistics::default(); pixel_count],
+            direct_sums: vec![],
+            albedo_sums: vec![],
+            normal_sums: vec![],
+        }
+    }
+
+    /// Also collect everything needed for the given passes. This only makes a difference for
+    /// passes which get collected from every sample. See [`Aov::is_accumulated`].
+    pub fn with_aovs(mut self, aovs: &[Aov]) -> Self {
+        let pixel_count = self.region.pixel_count();
+        if aovs.contains(&Aov::Direct) || aovs.contains(&Aov::Indirect) {
+            self.direct_sums = vec![Colour::ZERO; pixel_count];
+        }
+        if aovs.contains(&Aov::Albedo) {
+            self.albedo_sums = vec![Colour::ZERO; pixel_count];
+        }
+        if aovs.contains(&Aov::Normal) {
+            self.normal_sums = vec![Vec3::ZERO; pixel_count];
+        }
+        self
+    }
+
+    /// Create an empty film for the same image as this one, which collects the same passes,
+    /// but covers the given region instead.
+    pub fn new_like(&self, region: Region) -> Self {
+        let pixel_count = region.pixel_count();
+        let empty_unless_collected = |sums: &Vec<Colour>| {
+            if sums.is_empty() {
+                vec![]
+            } else {
+                vec![Colour::ZERO; pixel_count]
+            }
+        };
+
+        Self {
+            region,
+            colour_sums: vec![Colour::ZERO; pixel_count],
+            weight_sums: vec![0.; pixel_count],
+            statistics: vec![PixelStatistics::default(); pixel_count],
+            direct_sums: empty_unless_collected(&self.direct_sums),
+            albedo_sums: empty_unless_collected(&self.albedo_sums),
+            normal_sums: empty_unless_collected(&self.normal_sums),
+            filter_table: Arc::clone(&self.filter_table),
+            ..*self
+        }
+    }
+
+    /// Does this film collect everything needed for the given pass? This is always false for
+    /// passes which don't get collected from every sample.
+    pub fn collects(&self, aov: Aov) -> bool {
+        match aov {
+            Aov::Direct | Aov::Indirect => !self.direct_sums.is_empty(),
+            Aov::Albedo => !self.albedo_sums.is_empty(),
+            Aov::Normal => !self.normal_sums.is_empty(),
+            Aov::SampleCount => true,
+            Aov::Depth | Aov::Position | Aov::ObjectId | Aov::MaterialId => false,
+        }
+    }
+
+    /// How many pixels away from a sample its filter can reach. Samples can be anywhere in their
+    /// pixel, so this is a bit more than the radius of the filter.
+    pub fn filter_reach(&self) -> u32 {
+        (self.filter.radius + 0.5).ceil() as u32
+    }
+
+    /// Splat a pass of samples onto the film, where there is at most one sample in each pixel
+    /// of the given region, in row-major order.
+    ///
+    /// Rather than have every sample write to the pixels around it, every pixel reads the
+    /// samples around it, which gives the same result but lets us do every pixel in parallel.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of samples is not the same as the number of pixels in the region,
+    /// or if the region isn't inside the region of this film.
+    pub fn add_pass(&mut self, sample_region: Region, samples: &[Option<FilmSample>]) {
+        assert_eq!(
+            samples.len(),
+            sample_region.pixel_count(),
+            "There must be exactly one entry for each pixel"
+        );
+        assert_eq!(
+            sample_region.intersection(&self.region),
+            Some(sample_region),
+            "The samples must be inside the region of the film"
+        );
+
+        for (index, sample) in samples.iter().enumerate() {
+            if let Some(sample) = sample {
+                let (x, y) = sample_region.pixel(index);
+                let film_index = self.region.index(x, y);
+                self.statistics[film_index].record(sample.colour);
+
+                if !self.albedo_sums.is_empty() {
+                    self.albedo_sums[film_index] += sample.albedo;
+                }
+                if !self.normal_sums.is_empty() {
+                    self.normal_sums[film_index] += sample.normal;
+                }
+            }
+        }
+
+        if self.filter_table.is_empty() {
+            self.filter_table = filter_table(&self.filter);
+        }
+        let (radius, filter_table) = (self.filter.radius, &self.filter_table);
+        let filter_1d = |x: f64| {
+            if x.abs() > radius {
+                0.
+            } else {
+                // The nearest entry is close enough, since the table is so fine
+                filter_table[(x.abs() * FILTER_TABLE_RESOLUTION).round() as usize]
+            }
+        };
+
+        let reach = self.filter_reach();
+        let affected = sample_region.expand(reach, &self.region);
+        let region = self.region;
+
+        // The direct light only gets splatted if we're collecting it
+        let mut direct_rows: Vec<Option<&mut [Colour]>> = if self.direct_sums.is_empty() {
+            (0..region.height).map(|_| None).collect()
+        } else {
+            self.direct_sums
+                .chunks_mut(region.width as usize)
+                .map(Some)
+                .collect()
+        };
+
+        self.colour_sums
+            .par_chunks_mut(region.width as usize)
+            .zip(self.weight_sums.par_chunks_mut(region.width as usize))
+            .zip(direct_rows.par_iter_mut())
+            .enumerate()
+            .for_each(|(row, ((colour_row, weight_row), direct_row))| {
+                let y = region.y + row as u32;
+                if !(affected.y..affected.bottom()).contains(&y) {
+                    return;
+                }
+
+                for x in affected.x..affected.right() {
+                    let index = (x - region.x) as usize;
+                    let centre = (x as f64 + 0.5, y as f64 + 0.5);
+                    let Some(neighbourhood) = Region::new(x, y, 1, 1)
+                        .expand(reach, &region)
+                        .intersection(&sample_region)
+                    else {
+                        continue;
+                    };
+
+                    for j in neighbourhood.y..neighbourhood.bottom() {
+                        for i in neighbourhood.x..neighbourhood.right() {
+                            let Some(sample) = &samples[sample_region.index(i, j)] else {
+                                continue;
+                            };
+                            let weight = filter_1d(sample.position.0 - centre.0)
+                                * filter_1d(sample.position.1 - centre.1);
+
+                            if weight != 0. {
+                                colour_row[index] += sample.colour * weight;
+                                weight_row[index] += weight;
+                                if let Some(direct_row) = direct_row {
+                                    direct_row[index] += sample.direct * weight;
+                                }
+                            }
+                        }
+                    }
+                }
+            });
+    }
+
+    /// Add all the samples from another film onto this one. Anything outside the region of this
+    /// film gets ignored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the other film is for an image with a different size.
+    pub fn merge(&mut self, other: &Self) {
+        assert_eq!(
+            (self.width, self.height),
+            (other.width, other.height),
+            "Films can only be merged if they're for the same size of image"
+        );
+
+        let Some(overlap) = self.region.intersection(&other.region) else {
+            return;
+        };
+
+        for y in overlap.y..overlap.bottom() {
+            for x in overlap.x..overlap.right() {
+                let (index, other_index) = (self.region.index(x, y), other.region.index(x, y));
+                self.colour_sums[index] += other.colour_sums[other_index];
+                self.weight_sums[index] += other.weight_sums[other_index];
+                self.statistics[index] =
+                    self.statistics[index].merge(other.statistics[other_index]);
+
+                if !(self.direct_sums.is_empty() || other.direct_sums.is_empty()) {
+                    self.direct_sums[index] += other.direct_sums[other_index];
+                }
+                if !(self.albedo_sums.is_empty() || other.albedo_sums.is_empty()) {
+                    self.albedo_sums[index] += other.albedo_sums[other_index];
+                }
+                if !(self.normal_sums.is_empty() || other.normal_sums.is_empty()) {
+                    self.normal_sums[index] += other.normal_sums[other_index];
+                }
+            }
+        }
+    }
+
+    /// The width of the whole image.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of the whole image.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The region of the image covered by this film.
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Statistics about the samples taken within the given pixel. Pixels outside the region of
+    /// this film have no samples.
+    pub fn statistics(&self, x: u32, y: u32) -> PixelStatistics {
+        if self.region.contains(x, y) {
+            self.statistics[self.region.index(x, y)]
+        } else {
+            PixelStatistics::default()
+        }
+    }
+
+    /// How many samples have been taken within the given pixel.
+    pub fn sample_count(&self, x: u32, y: u32) -> u32 {
+        self.statistics(x, y).count
+    }
+
+    /// Make a greyscale image of how many samples were taken within each pixel, where white is
+    /// the most samples of any pixel.
+    pub fn sample_count_image(&self) -> GrayImage {
+        let most_samples = self
+            .statistics
+            .iter()
+            .map(|statistics| statistics.count)
+            .max()
+            .unwrap_or(0)
+            .max(1);
+
+        GrayImage::from_fn(self.width, self.height, |x, y| {
+            let proportion = self.sample_count(x, y) as f64 / most_samples as f64;
+            Luma([(proportion * 255.).round() as u8])
+        })
+    }
+
+    /// Resolve the samples so far into a linear float image of the whole image.
+    pub fn to_image(&self) -> Rgb32FImage {
+        self.resolve(|index| self.filtered(&self.colour_sums, index))
+    }
+
+    /// Resolve the given pass into a linear float image of the whole image, if it's collected by
+    /// this film. See [`Aov`] for what each pass holds.
+    pub fn aov_image(&self, aov: Aov) -> Option<Rgb32FImage> {
+        if !self.collects(aov) {
+            return None;
+        }
+
+        let averaged = |sums: &[Colour], index: usize| {
+            sums[index] / (self.statistics[index].count as f64).max(1.)
+        };
+
+        Some(match aov {
+            Aov::Direct => self.resolve(|index| self.filtered(&self.direct_sums, index)),
+            Aov::Indirect => self.resolve(|index| {
+                self.filtered(&self.colour_sums, index) - self.filtered(&self.direct_sums, index)
+            }),
+            Aov::Albedo => self.resolve(|index| averaged(&self.albedo_sums, index)),
+            Aov::Normal => self.resolve(|index| averaged(&self.normal_sums, index)),
+            Aov::SampleCount => self.resolve(|index| v!(self.statistics[index].count as f64)),
+            Aov::Depth | Aov::Position | Aov::ObjectId | Aov::MaterialId => {
+                unreachable!("Films don't collect {aov:?}")
+            }
+        })
+    }
+
+    /// Divide the given weighted sum for the pixel with the given index in the region by the
+    /// total weight of the pixel.
+    fn filtered(&self, sums: &[Colour], index: usize) -> Colour {
+        let weight_sum = self.weight_sums[index];
+
+        // Filters with negative lobes can give a pixel a negative total weight when it only has a
+        // few samples, and dividing by that would flip its colour
+        if weight_sum > 0. {
+            sums[index] / weight_sum
+        } else {
+            Colour::ZERO
+        }
+    }
+
+    /// Make a float image of the whole image, where each pixel in the region gets the colour
+    /// for its index in the region, and the rest of the image is black.
+    fn resolve(&self, colour: impl Fn(usize) -> Colour + Sync) -> Rgb32FImage {
+        let mut img = Rgb32FImage::new(self.width, self.height);
+
+        img.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+            if self.region.contains(x, y) {
+                *pixel = colour(self.region.index(x, y)).into();
+            }
+        });
+
+        img
+    }
+}
+
+/// Evaluate the given filter at evenly spaced distances from zero to its radius.
+fn filter_table(filter: &Filter) -> Arc<[f64]> {
+    let table_size = (filter.radius * FILTER_TABLE_RESOLUTION).ceil() as usize + 1;
+    (0..table_size)
+        .map(|i| filter.evaluate(i as f64 / FILTER_TABLE_RESOLUTION))
+        .collect()
+}
@@ -0,0 +1,224 @@
+//! This module provides the [`Filter`] type, which decides how much each sample contributes to
+//! the pixels around it.
+
+use core::f64::consts::PI;
+use serde::{Deserialize, Serialize};
+
+/// The shape of a pixel reconstruction filter.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Deserialize, Serialize)]
+pub enum FilterKind {
+    /// Every sample within the radius counts the same. With the default radius, this just
+    /// averages the samples in each pixel.
+    #[default]
+    Box,
+
+    /// The weight falls off linearly with distance.
+    Tent,
+
+    /// A Gaussian curve with a standard deviation of a third of the radius, shifted down so that
+    /// it reaches zero at the radius.
+    Gaussian,
+
+    /// The Mitchell–Netravali cubic with `B = C = 1/3`. This is sharper than the Gaussian, with a
+    /// slight negative lobe.
+    Mitchell,
+
+    /// A sinc windowed by a wider sinc, with as many lobes as the radius. This is the sharpest,
+    /// but it can cause ringing around bright edges.
+    Lanczos,
+}
+
+impl FilterKind {
+    /// The radius that this filter usually uses, in pixels.
+    pub fn default_radius(self) -> f64 {
+        match self {
+            Self::Box => 0.5,
+            Self::Tent => 1.,
+            Self::Gaussian => 1.5,
+            Self::Mitchell => 2.,
+            Self::Lanczos => 3.,
+        }
+    }
+}
+
+/// A pixel reconstruction filter.
+///
+/// Each sample gets splatted onto every pixel whose centre is within the radius of it, weighted
+/// by the filter, and each pixel is the weighted average of all the samples splatted onto it.
+/// Wider filters give smoother edges with less aliasing, but a blurrier image.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Filter {
+    /// The shape of the filter.
+    pub kind: FilterKind,
+
+    /// How far the filter reaches from a sample in each direction, in pixels.
+    pub radius: f64,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::new(FilterKind::default())
+    }
+}
+
+impl Filter {
+    /// Create a filter of the given kind with its default radius.
+    pub fn new(kind: FilterKind) -> Self {
+        Self {
+            kind,
+            radius: kind.default_radius(),
+        }
+    }
+
+    /// Use the given radius instead of the default one.
+    pub fn with_radius(mut self, radius: f64) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    /// Evaluate the filter in one dimension, at the given distance from its centre. The filter
+    /// is separable, so the weight in two dimensions is just the product of the weights in each
+    /// dimension.
+    pub fn evaluate(&self, x: f64) -> f64 {
+        let x = x.abs();
+        let radius = self.radius;
+        if x > radius {
+            return 0.;
+        }
+
+        match self.kind {
+            FilterKind::Box => 1.,
+            FilterKind::Tent => 1. - x / radius,
+            FilterKind::Gaussian => {
+                let gaussian = |x: f64| {
+                    let sigma = radius / 3.;
+                    (-x * x / (2. * sigma * sigma)).exp()
+                };
+                (gaussian(x) - gaussian(radius)).max(0.)
+            }
+            FilterKind::Mitchell => mitchell(2. * x / radius),
+            FilterKind::Lanczos => sinc(x) * sinc(x / radius),
+        }
+    }
+}
+
+/// The Mitchell–Netravali cubic with `B = C = 1/3`, which is non-zero in `[-2, 2]`.
+fn mitchell(x: f64) -> f64 {
+    const B: f64 = 1. / 3.;
+    const C: f64 = 1. / 3.;
+
+    let x = x.abs();
+    let value = if x < 1. {
+        (12. - 9. * B - 6. * C) * x.powi(3) + (-18. + 12. * B + 6. * C) * x.powi(2) + (6. - 2. * B)
+    } else if x < 2. {
+        (-B - 6. * C) * x.powi(3)
+            + (6. * B + 30. * C) * x.powi(2)
+            + (-12. * B - 48. * C) * x
+            + (8. * B + 24. * C)
+    } else {
+        0.
+    };
+
+    value / 6.
+}
+
+/// The normalised sinc function, `sin(πx) / πx`.
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        1.
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        film::{Film, FilmSample},
+        random::seeded_rng,
+        tile::Region,
+        vector::{Colour, Vec3},
+    };
+    use clap::ValueEnum;
+    use rand::Rng;
+
+    /// Every kind of filter, with its default radius and a couple of others.
+    fn filters() -> impl Iterator<Item = Filter> {
+        FilterKind::value_variants().iter().flat_map(|&kind| {
+            [
+                Filter::new(kind),
+                Filter::new(kind).with_radius(0.75),
+                Filter::new(kind).with_radius(2.5),
+            ]
+        })
+    }
+
+    #[test]
+    fn filters_are_symmetric() {
+        for filter in filters() {
+            for i in 0..=100 {
+                let x = filter.radius * i as f64 / 100.;
+                assert_eq!(filter.evaluate(x), filter.evaluate(-x), "{filter:?} at {x}");
+            }
+        }
+    }
+
+    #[test]
+    fn filters_are_zero_outside_their_radius() {
+        for filter in filters() {
+            assert!(filter.evaluate(0.) > 0., "{filter:?}");
+            for i in 1..=100 {
+                let x = filter.radius + i as f64 / 20.;
+                assert_eq!(filter.evaluate(x), 0., "{filter:?} at {x}");
+                assert_eq!(filter.evaluate(-x), 0., "{filter:?} at {}", -x);
+            }
+        }
+    }
+
+    #[test]
+    fn the_default_box_filter_averages_the_samples_in_each_pixel() {
+        let (width, height) = (5, 3);
+        let region = Region::full(width, height);
+        let mut film = Film::new(width, height, region, Filter::default());
+        let mut sums = vec![Colour::ZERO; region.pixel_count()];
+
+        let mut rng = seeded_rng(0);
+        let passes = 8;
+        for _ in 0..passes {
+            let samples: Vec<_> = (0..region.pixel_count())
+                .map(|index| {
+                    let (x, y) = region.pixel(index);
+                    let colour = Vec3::new(rng.gen(), rng.gen(), rng.gen());
+                    sums[index] += colour;
+
+                    // Samples on the very edge of a pixel would count for both pixels
+                    Some(FilmSample {
+                        position: (
+                            x as f64 + rng.gen_range(0.001..0.999),
+                            y as f64 + rng.gen_range(0.001..0.999),
+                        ),
+                        colour,
+                        direct: Colour::ZERO,
+                        albedo: Colour::ZERO,
+                        normal: Vec3::ZERO,
+                    })
+                })
+                .collect();
+            film.add_pass(region, &samples);
+        }
+
+        let image = film.to_image();
+        for (index, sum) in sums.into_iter().enumerate() {
+            let (x, y) = region.pixel(index);
+            let expected = sum / passes as f64;
+            let actual = image.get_pixel(x, y).0;
+            for (actual, expected) in actual.into_iter().zip([expected.x, expected.y, expected.z]) {
+                assert!(
+                    (actual as f64 - expected).abs() < 1e-6,
+                    "pixel ({x}, {y}) is {actual} rather than {expected}"
+                );
+            }
+        }
+    }
+}
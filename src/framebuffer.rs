@@ -0,0 +1,71 @@
+//! This module provides the [`Framebuffer`] type.
+
+use image::Rgb32FImage;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+/// An image which can be safely shared between the thread rendering it and any threads that want
+/// to look at it while it's being rendered.
+///
+/// This is double buffered. The renderer accumulates samples into its own image, and then
+/// publishes a snapshot of that image here after every pass. Readers get a cheap reference-counted
+/// snapshot which is never modified while they're holding it.
+#[derive(Debug)]
+pub struct Framebuffer {
+    /// The most recently published snapshot.
+    snapshot: Mutex<Arc<Rgb32FImage>>,
+
+    /// How many times a snapshot has been published. Readers can use this to tell whether the
+    /// image has changed since they last looked at it.
+    generation: AtomicU64,
+}
+
+impl Framebuffer {
+    /// Create a new black framebuffer with the given size.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            snapshot: Mutex::new(Arc::new(Rgb32FImage::new(width, height))),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// The width of the image.
+    pub fn width(&self) -> u32 {
+        self.snapshot().width()
+    }
+
+    /// The height of the image.
+    pub fn height(&self) -> u32 {
+        self.snapshot().height()
+    }
+
+    /// Publish a new snapshot of the image.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given image is not the same size as this framebuffer.
+    pub fn publish(&self, image: &Rgb32FImage) {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        assert_eq!(
+            snapshot.dimensions(),
+            image.dimensions(),
+            "The published image must be the same size as the framebuffer"
+        );
+
+        // If nobody else is holding the old snapshot, then we can reuse its memory
+        Arc::make_mut(&mut snapshot).copy_from_slice(image);
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+
+    /// Get the most recently published snapshot of the image.
+    pub fn snapshot(&self) -> Arc<Rgb32FImage> {
+        Arc::clone(&self.snapshot.lock().unwrap())
+    }
+
+    /// How many snapshots have been published so far?
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+}
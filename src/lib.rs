@@ -0,0 +1,31 @@
+//! This crate is a simple raytracer based on [this UWCS project](https://rs118.uwcs.co.uk/raytracer.html).
+//!
+//! The easiest way to render something is to build a [`Scene`](object::Scene) and a
+//! [`Camera`](camera::Camera), and then call [`render`]. Use a [`Renderer`] if you want to watch
+//! the image as it renders, report progress, or cancel the render.
+
+pub mod aov;
+pub mod camera;
+pub mod checkpoint;
+pub mod denoise;
+pub mod environment;
+pub mod film;
+pub mod filter;
+pub mod framebuffer;
+pub mod material;
+pub mod object;
+pub mod output;
+pub mod preview;
+pub mod random;
+pub mod ray;
+pub mod render;
+pub mod sampler;
+pub mod scene_file;
+pub mod texture;
+pub mod tile;
+pub mod tonemap;
+pub mod vector;
+
+pub use self::render::{
+    render, CancelToken, CentreHit, Progress, RenderOpts, RenderStats, Renderer,
+};
@@ -1,39 +1,49 @@
-//! This crate is a simple raytracer based on [this UWCS project](https://rs118.uwcs.co.uk/raytracer.html).
+//! This is the command line interface to the raytracer.
 
-#![cfg_attr(debug_assertions, allow(dead_code))]
-
-mod camera;
-mod material;
-mod object;
-mod ray;
-mod vector;
-
-use self::{
-    camera::{Camera, CameraOpts},
-    object::random_scene,
-    vector::{v, Colour},
-};
 use clap::Parser;
-use color_eyre::{eyre::Context, Result};
-use image::{DynamicImage, Rgb32FImage, RgbImage};
-use indicatif::{ProgressBar, ProgressDrawTarget, ProgressIterator, ProgressStyle};
-use rand::{distributions::Distribution, thread_rng};
-use rayon::iter::ParallelIterator;
+use color_eyre::{
+    eyre::{ensure, Context},
+    Report, Result,
+};
+use image::Rgb32FImage;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use raytracer::{
+    aov::Aov,
+    camera::Camera,
+    checkpoint::{Checkpoint, CheckpointOpts},
+    denoise::DenoiseOpts,
+    environment::SharedEnvironment,
+    film::Film,
+    filter::{Filter, FilterKind},
+    object::{identify_objects, random_scene, Bvh},
+    output::{aov_path, save_aov, save_image},
+    preview::{draw_image, draw_text, Viewport},
+    sampler::SamplerKind,
+    scene_file::{CameraDescription, EnvironmentDescription, ObjectDescription, SceneFile},
+    tile::{Region, TileOrder},
+    tonemap::{ToneMap, ViewTransform},
+    vector::{Colour, Point, Vec3},
+    CancelToken, CentreHit, Progress, RenderOpts, RenderStats, Renderer,
+};
 use std::{
+    env, mem,
     num::NonZeroU32,
-    sync::Arc,
+    path::{Path, PathBuf},
+    process,
+    sync::{Arc, Condvar, Mutex},
     thread,
     time::{Duration, Instant},
 };
 use winit::{
-    dpi::LogicalSize,
-    event::{Event, WindowEvent},
+    dpi::{LogicalSize, PhysicalPosition},
+    event::{ElementState, Event, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
+    keyboard::{Key, NamedKey},
     window::WindowBuilder,
 };
 
-#[derive(clap::Parser)]
-#[command(author, version, about)]
+#[derive(Clone, clap::Parser)]
+#[command(author, version, about, args_override_self = true)]
 struct Args {
     /// The full width of the image.
     #[arg(long, short, default_value_t = 1920)]
@@ -47,13 +57,187 @@ struct Args {
     #[arg(long, short, default_value_t = 100)]
     samples: u16,
 
-    /// How many times should each ray bounce?
+    /// Use adaptive sampling with this noise threshold. Each pixel stops getting samples once the
+    /// relative error of its brightness falls below this, like 0.01 for 1%, so smooth areas of
+    /// the image finish early. Every pixel still gets at least `--samples` samples.
+    #[arg(long)]
+    noise_threshold: Option<f64>,
+
+    /// The most samples that any pixel can get with adaptive sampling. This defaults to four
+    /// times `--samples`.
+    #[arg(long)]
+    max_samples: Option<u16>,
+
+    /// Stop rendering after about this long, like `90s` or `10m`, and save the image with all
+    /// the samples taken so far. The render still stops when it reaches `--samples`, so give a
+    /// large sample count to use all of the time.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    time_limit: Option<Duration>,
+
+    /// Save the progress of the render to this file every so often, and at the end, so that the
+    /// render can be carried on with `--resume`.
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// How long to wait between checkpoints, like `30s` or `5m`.
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "5m")]
+    checkpoint_interval: Duration,
+
+    /// Carry on rendering from this checkpoint. The render uses the same arguments as when the
+    /// checkpoint was made, but any arguments given now replace them, so this can add more
+    /// samples to a finished render with a bigger `--samples`. New checkpoints get saved to the
+    /// same file unless `--checkpoint` is given.
+    #[arg(long)]
+    resume: Option<PathBuf>,
+
+    /// Also save a greyscale image of how many samples each pixel got to this path, where white
+    /// is the most samples of any pixel.
+    #[arg(long)]
+    sample_count_image: Option<PathBuf>,
+
+    /// Only render this part of the image, given as `x,y,width,height` in pixels, and leave the
+    /// rest black. The image is still the full size, so renders of different regions can be put
+    /// together afterwards.
+    #[arg(long)]
+    region: Option<Region>,
+
+    /// Render the image in square tiles of this size, where each tile gets all of its samples
+    /// before moving on to the next one. Finished tiles show up straight away, rather than the
+    /// whole image slowly getting less noisy.
+    #[arg(long)]
+    tile_size: Option<u32>,
+
+    /// The order to render the tiles in when using `--tile-size`.
+    #[arg(long, value_enum, default_value_t = TileOrder::Spiral)]
+    tile_order: TileOrder,
+
+    /// Denoise the image once it's rendered, which makes renders with only a few samples per
+    /// pixel much smoother, at the cost of some fine detail. The denoiser is guided by the albedo
+    /// and normals of the scene, so it keeps edges and textures sharp.
+    #[arg(long)]
+    denoise: bool,
+
+    /// Extra passes to save alongside the image, separated by commas, like `albedo,normal,depth`.
+    /// Each pass goes next to the output with its name added, like `out.albedo.exr` for
+    /// `out.exr`. Saving to `.exr` or `.hdr` keeps the raw values of each pass.
+    #[arg(long = "aov", value_enum, value_delimiter = ',')]
+    aovs: Vec<Aov>,
+
+    /// The maximum number of times each ray can bounce.
     #[arg(long, short, default_value_t = 50)]
     bounces: u16,
 
-    /// The path to the output image file.
+    /// How many times each ray bounces before it can be randomly terminated by Russian roulette.
+    #[arg(long, default_value_t = 3)]
+    min_bounces: u16,
+
+    /// The path to the output image file. Saving to a `.exr` or `.hdr` file will keep the full
+    /// dynamic range of the image.
     #[arg(long, short, default_value = "./out.png")]
     output: String,
+
+    /// The path to a TOML scene file to render. If this is not given, then we render a random
+    /// scene.
+    #[arg(long)]
+    scene: Option<PathBuf>,
+
+    /// The point that the camera looks from, like `13,2,3`, overriding the scene file.
+    #[arg(long, allow_hyphen_values = true)]
+    look_from: Option<Point>,
+
+    /// The point that the camera looks at, overriding the scene file.
+    #[arg(long, allow_hyphen_values = true)]
+    look_at: Option<Point>,
+
+    /// The vector which decides which way is up for the camera, overriding the scene file.
+    #[arg(long, allow_hyphen_values = true)]
+    view_up: Option<Vec3>,
+
+    /// The vertical field of view of the camera in degrees, overriding the scene file.
+    #[arg(long)]
+    fov: Option<f64>,
+
+    /// The width of the camera's aperture, overriding the scene file. Wider apertures blur
+    /// things which are out of focus more.
+    #[arg(long)]
+    aperture: Option<f64>,
+
+    /// The distance from the camera to the plane which is in focus, overriding the scene file.
+    #[arg(long)]
+    focus_distance: Option<f64>,
+
+    /// Render without a preview window, only showing progress in the terminal. This is the
+    /// default if there is no display available.
+    #[arg(long)]
+    headless: bool,
+
+    /// The seed for all the random numbers, including the random scene. Rendering with the same
+    /// seed always gives exactly the same image.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// The sampler which decides the random numbers for each sample. The low-discrepancy
+    /// samplers spread the samples out more evenly, so the image converges faster.
+    #[arg(long, value_enum, default_value_t = SamplerKind::Sobol)]
+    sampler: SamplerKind,
+
+    /// The filter used to splat each sample onto the pixels around it. Wider filters give
+    /// smoother edges but a blurrier image.
+    #[arg(long, value_enum, default_value_t = FilterKind::Box)]
+    filter: FilterKind,
+
+    /// The radius of the filter in pixels. Each filter has its own default radius.
+    #[arg(long)]
+    filter_radius: Option<f64>,
+
+    /// The tone mapping curve used to display the image and to save low dynamic range images.
+    #[arg(long, value_enum, default_value_t = ToneMap::Srgb)]
+    tone_map: ToneMap,
+
+    /// The exposure adjustment in stops, applied before tone mapping. Each stop doubles the
+    /// brightness.
+    #[arg(long, default_value_t = 0., allow_negative_numbers = true)]
+    exposure: f64,
+
+    /// The environment which lights the scene from far away, overriding the one from the scene
+    /// file. This can be `black`, `sky`, a colour like `0.1,0.1,0.2`, or the path to an
+    /// equirectangular image like an `.exr` or `.hdr` file.
+    #[arg(long)]
+    environment: Option<EnvironmentDescription>,
+
+    /// How far to rotate the environment image anticlockwise around the Y axis, in degrees.
+    #[arg(long, allow_negative_numbers = true)]
+    environment_rotation: Option<f64>,
+
+    /// How much to scale the brightness of the environment image by.
+    #[arg(long)]
+    environment_intensity: Option<f64>,
+}
+
+impl Args {
+    /// The view transform chosen by these arguments.
+    fn view_transform(&self) -> ViewTransform {
+        ViewTransform {
+            tone_map: self.tone_map,
+            exposure: self.exposure,
+        }
+    }
+
+    /// The camera from the scene file, or the default camera if there isn't one, with any
+    /// changes given by these arguments.
+    fn camera_description(&self, scene_file: Option<&SceneFile>) -> CameraDescription {
+        let mut description = scene_file
+            .map(|scene_file| scene_file.camera.clone())
+            .unwrap_or_default();
+
+        description.look_from = self.look_from.unwrap_or(description.look_from);
+        description.look_at = self.look_at.unwrap_or(description.look_at);
+        description.view_up = self.view_up.unwrap_or(description.view_up);
+        description.vertical_fov_degrees = self.fov.unwrap_or(description.vertical_fov_degrees);
+        description.aperture_width = self.aperture.unwrap_or(description.aperture_width);
+        description.focus_distance = self.focus_distance.unwrap_or(description.focus_distance);
+        description
+    }
 }
 
 fn main() -> Result<()> {
@@ -61,24 +245,28 @@ fn main() -> Result<()> {
 
     let args = Args::parse();
 
-    let look_from = v!(13, 2, 3);
-    let look_at = v!(0, 0, 0);
-
-    let camera = Camera::from(CameraOpts {
-        width: args.width,
-        height: args.height,
-        vertical_fov_degrees: 20.,
-        look_from,
-        look_at,
-        view_up: v!(0, 1, 0),
-        aperture_width: 0.1,
-        focus_distance: 10.,
-    });
-
-    let float_img = &mut Rgb32FImage::new(args.width, args.height) as *mut _;
-    let scene = random_scene();
+    // When resuming, we parse the original arguments followed by the new ones, so that the new
+    // ones take precedence. The original arguments might have come from resuming an earlier
+    // checkpoint themselves, so we leave out where they resumed from and saved to, otherwise the
+    // arguments would keep growing with every resume
+    let (args, arguments, checkpoint) = match &args.resume {
+        Some(path) => {
+            let checkpoint = Checkpoint::load(path).wrap_err("When trying to load checkpoint")?;
+            let arguments: Vec<String> = without_checkpoint_paths(&checkpoint.arguments)
+                .chain(env::args().skip(1))
+                .collect();
+            (Args::parse_from(&arguments), arguments, Some(checkpoint))
+        }
+        None => (args, env::args().collect(), None),
+    };
 
-    let offset_distribution = rand::distributions::Uniform::new_inclusive(-0.5, 0.5);
+    let scene_file = args.scene.as_ref().map(SceneFile::load).transpose()?;
+    let scene = match &scene_file {
+        Some(scene_file) => scene_file.build_scene()?,
+        None => random_scene(args.seed),
+    };
+    let environment = build_environment(&args, scene_file.as_ref())?;
+    let scene = Bvh::new(identify_objects(scene));
 
     let progress_bar = ProgressBar::with_draw_target(
         Some(args.samples as u64),
@@ -92,127 +280,964 @@ fn main() -> Result<()> {
         .progress_chars("=> "),
     );
 
-    let preview_scale_factor = if args.width > args.height {
-        args.width as f64 / 1280.
+    let mut renderer = build_renderer(&args, arguments.clone(), &environment, &progress_bar)?;
+
+    if let Some(checkpoint) = checkpoint {
+        println!(
+            "Resuming from a checkpoint with {} samples per pixel done",
+            checkpoint.samples_done
+        );
+        renderer = renderer
+            .resume_from(checkpoint)
+            .wrap_err("When trying to resume from checkpoint")?;
+    }
+
+    // The first Ctrl-C stops the render after the current pass and saves the image, and the
+    // second one quits straight away
+    ctrlc::set_handler({
+        let cancel_token = renderer.cancel_token();
+        let progress_bar = progress_bar.clone();
+        move || {
+            if cancel_token.is_cancelled() {
+                process::exit(130);
+            }
+            progress_bar.println(
+                "Stopping after the current pass, press Ctrl-C again to quit without saving",
+            );
+            cancel_token.cancel();
+        }
+    })
+    .wrap_err("When trying to handle Ctrl-C")?;
+
+    let event_loop = if args.headless {
+        None
+    } else if !display_available() {
+        println!("No display found, so rendering without a preview window");
+        None
     } else {
-        args.height as f64 / 720.
+        match EventLoop::new() {
+            Ok(event_loop) => Some(event_loop),
+            Err(error) => {
+                println!("Couldn't open a preview window ({error}), so rendering without one");
+                None
+            }
+        }
     };
 
-    let preview_width = args.width as f64 / preview_scale_factor;
-    let preview_height = args.height as f64 / preview_scale_factor;
+    match event_loop {
+        Some(event_loop) => run_with_preview(
+            event_loop,
+            arguments,
+            scene_file,
+            scene,
+            renderer,
+            environment,
+            progress_bar,
+        ),
+        None => {
+            let camera_description = args.camera_description(scene_file.as_ref());
+            let camera = Camera::from(camera_description.to_camera_opts(args.width, args.height));
+            render_and_save(&args, &camera, &scene, &renderer, &progress_bar)
+        }
+    }
+}
+
+/// The given command line arguments without any `--resume` or `--checkpoint` arguments, or
+/// their values.
+fn without_checkpoint_paths(arguments: &[String]) -> impl Iterator<Item = String> + '_ {
+    const FLAGS: [&str; 2] = ["--resume", "--checkpoint"];
+
+    let mut skip_value = false;
+    arguments
+        .iter()
+        .filter(move |argument| {
+            if mem::take(&mut skip_value) {
+                return false;
+            }
+
+            match argument.split_once('=') {
+                Some((flag, _)) => !FLAGS.contains(&flag),
+                None if FLAGS.contains(&argument.as_str()) => {
+                    skip_value = true;
+                    false
+                }
+                None => true,
+            }
+        })
+        .cloned()
+}
+
+/// Make a renderer from the given arguments, which get saved in any checkpoints. The renderer
+/// reports its progress to the given progress bar.
+fn build_renderer(
+    args: &Args,
+    arguments: Vec<String>,
+    environment: &SharedEnvironment,
+    progress_bar: &ProgressBar,
+) -> Result<Renderer> {
+    let mut filter = Filter::new(args.filter);
+    if let Some(radius) = args.filter_radius {
+        ensure!(
+            radius > 0.,
+            "The filter radius must be positive, not {radius}"
+        );
+        filter = filter.with_radius(radius);
+    }
+
+    if let Some(threshold) = args.noise_threshold {
+        ensure!(
+            threshold > 0.,
+            "The noise threshold must be positive, not {threshold}"
+        );
+    }
+
+    if let Some(region) = args.region {
+        ensure!(
+            region.right() <= args.width && region.bottom() <= args.height,
+            "The region {region:?} must be inside the {}x{} image",
+            args.width,
+            args.height
+        );
+    }
+
+    if let Some(tile_size) = args.tile_size {
+        ensure!(
+            tile_size > 0,
+            "The tile size must be positive, not {tile_size}"
+        );
+    }
+
+    let mut renderer = Renderer::new(RenderOpts {
+        width: args.width,
+        height: args.height,
+        samples: args.samples,
+        max_samples: args.max_samples.unwrap_or(args.samples.saturating_mul(4)),
+        noise_threshold: args.noise_threshold,
+        bounces: args.bounces,
+        min_bounces: args.min_bounces,
+        sampler: args.sampler,
+        filter,
+        time_limit: args.time_limit,
+        region: args.region,
+        tile_size: args.tile_size,
+        tile_order: args.tile_order,
+        aovs: args.aovs.clone(),
+        denoise: args.denoise.then(DenoiseOpts::default),
+        seed: args.seed,
+    })
+    .with_environment(Arc::clone(environment))
+    .with_progress_callback(show_progress(progress_bar));
+
+    if let Some(path) = args.checkpoint.clone().or_else(|| args.resume.clone()) {
+        let progress_bar = progress_bar.clone();
+        let opts = CheckpointOpts {
+            interval: args.checkpoint_interval,
+            arguments,
+        };
+
+        renderer = renderer.with_checkpoints(opts, move |checkpoint| {
+            // A failed checkpoint shouldn't throw away the render, so we just carry on
+            if let Err(error) = checkpoint.save(&path) {
+                progress_bar.println(format!("Couldn't save checkpoint: {error:?}"));
+            }
+        });
+    }
+
+    Ok(renderer)
+}
+
+/// Make a progress callback which shows the progress of a render on the given progress bar.
+fn show_progress(progress_bar: &ProgressBar) -> impl Fn(&Progress) + Send + Sync + 'static {
+    let progress_bar = progress_bar.clone();
+    move |progress| {
+        if progress.total_tiles > 0 {
+            progress_bar.set_length(progress.total_tiles as u64);
+            progress_bar.set_position(progress.tiles_done as u64);
+        } else {
+            progress_bar.set_length(progress.total_samples as u64);
+            progress_bar.set_position(progress.samples_done as u64);
+        }
+
+        let mut message = format!("- {:.2} bounces per path", progress.stats.mean_bounces());
+        if progress.converged_pixels > 0 {
+            message += &format!(
+                " - {:.1}% of pixels converged",
+                100. * progress.converged_pixels as f64 / progress.total_pixels as f64
+            );
+        }
+        progress_bar.set_message(message);
+    }
+}
+
+/// Build the environment given on the command line, or else the one from the scene file.
+fn build_environment(args: &Args, scene_file: Option<&SceneFile>) -> Result<SharedEnvironment> {
+    let (mut description, directory) = match (&args.environment, scene_file) {
+        (Some(description), _) => (description.clone(), Path::new("")),
+        (None, Some(scene_file)) => (
+            scene_file.environment.clone(),
+            scene_file.directory.as_path(),
+        ),
+        (None, None) => (EnvironmentDescription::default(), Path::new("")),
+    };
+
+    if let EnvironmentDescription::Map {
+        rotation_degrees,
+        intensity,
+        ..
+    } = &mut description
+    {
+        if let Some(rotation) = args.environment_rotation {
+            *rotation_degrees = rotation;
+        }
+        if let Some(scale) = args.environment_intensity {
+            *intensity = scale;
+        }
+    }
+
+    description
+        .build(directory)
+        .wrap_err("When trying to build the environment")
+}
+
+/// Is there a display that we could open a preview window on?
+///
+/// On Linux and the BSDs, we check for an X11 or Wayland display. We assume that every other
+/// platform always has a display.
+fn display_available() -> bool {
+    if cfg!(all(unix, not(target_os = "macos"), not(target_os = "ios"))) {
+        ["DISPLAY", "WAYLAND_DISPLAY", "WAYLAND_SOCKET"]
+            .iter()
+            .any(|var| env::var_os(var).is_some_and(|value| !value.is_empty()))
+    } else {
+        true
+    }
+}
+
+/// Render the scene, then save the image.
+fn render_and_save(
+    args: &Args,
+    camera: &Camera,
+    scene: &Bvh,
+    renderer: &Renderer,
+    progress_bar: &ProgressBar,
+) -> Result<()> {
+    println!("Rendering scene...");
+    let (film, stats, time_taken) = render(camera, scene, renderer, progress_bar);
+    let image = finish(args, renderer, &film, &stats, time_taken);
+    save(args, camera, scene, renderer, &film, &image)
+}
+
+/// Render the scene, and return the film along with how long it took.
+fn render(
+    camera: &Camera,
+    scene: &Bvh,
+    renderer: &Renderer,
+    progress_bar: &ProgressBar,
+) -> (Film, RenderStats, Duration) {
+    let start_time = Instant::now();
+    let (film, stats) = renderer.render_to_film(scene, camera);
+    progress_bar.finish();
+
+    (film, stats, start_time.elapsed())
+}
+
+/// Report how a render went, and turn its film into the final image.
+fn finish(
+    args: &Args,
+    renderer: &Renderer,
+    film: &Film,
+    stats: &RenderStats,
+    time_taken: Duration,
+) -> Rgb32FImage {
+    if renderer.cancel_token().is_cancelled() {
+        println!("Rendering was stopped early after {time_taken:?}");
+    } else {
+        println!("Rendering took {time_taken:?}");
+    }
+    print_stats(stats, time_taken);
+    if args.noise_threshold.is_some() {
+        println!(
+            "Adaptive sampling took {:.1} samples per pixel on average",
+            stats.paths as f64 / film.region().pixel_count() as f64
+        );
+    }
+
+    let denoise_start_time = Instant::now();
+    let image = renderer.finish(film);
+    if args.denoise {
+        println!("Denoising took {:?}", denoise_start_time.elapsed());
+    }
+    image
+}
+
+/// Save the final image of a render, along with any extra passes.
+fn save(
+    args: &Args,
+    camera: &Camera,
+    scene: &Bvh,
+    renderer: &Renderer,
+    film: &Film,
+    image: &Rgb32FImage,
+) -> Result<()> {
+    save_image(image, &args.output, &args.view_transform())
+        .wrap_err("When trying to save image buffer")?;
+    println!("Rendered to {}", args.output);
+
+    for (aov, image) in renderer.render_aovs(scene, camera, film) {
+        let path = aov_path(&args.output, aov);
+        save_aov(aov, &image, &path, &args.view_transform())
+            .wrap_err_with(|| format!("When trying to save the {} pass", aov.name()))?;
+        println!("Saved the {} pass to {}", aov.name(), path.display());
+    }
+
+    if let Some(path) = &args.sample_count_image {
+        film.sample_count_image().save(path).wrap_err_with(|| {
+            format!(
+                "When trying to save sample count image to {}",
+                path.display()
+            )
+        })?;
+        println!("Saved sample counts to {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// Print a summary of the paths traced during a render.
+fn print_stats(stats: &RenderStats, time_taken: Duration) {
+    let percentage = |count: u64| 100. * count as f64 / stats.paths.max(1) as f64;
+
+    println!(
+        "Traced {} paths with {:.2} bounces on average and at most {}",
+        stats.paths,
+        stats.mean_bounces(),
+        stats.longest_path
+    );
+    println!(
+        "Paths ended by escaping: {:.1}%, absorption: {:.1}%, Russian roulette: {:.1}%, \
+         bounce limit: {:.1}%",
+        percentage(stats.escaped),
+        percentage(stats.absorbed),
+        percentage(stats.russian_roulette),
+        percentage(stats.max_bounces)
+    );
+    println!(
+        "Cast {} rays including shadow rays, at {:.2} million per second",
+        stats.rays,
+        stats.rays_per_second(time_taken) / 1e6
+    );
+}
+
+/// The camera of the preview window, which can be moved while the scene is rendering.
+#[derive(Debug)]
+struct Viewpoint {
+    /// The camera that the next render should use.
+    camera: CameraDescription,
+
+    /// How many times the camera has moved, so that the render thread can tell whether the image
+    /// that it's rendering is out of date.
+    moves: u64,
+
+    /// The token which cancels the render that's currently running.
+    cancel_token: CancelToken,
+
+    /// Whether the image should be saved once the current render finishes, or straight away if
+    /// it has already finished.
+    save_requested: bool,
+}
+
+/// What the HUD in the preview window knows about the render that's currently running.
+#[derive(Clone, Copy, Debug, Default)]
+struct HudStatus {
+    /// The latest progress of the render, if it has finished a pass yet.
+    progress: Option<Progress>,
+
+    /// How many rays per second were cast between the last two progress reports.
+    rays_per_second: Option<f64>,
+}
+
+impl HudStatus {
+    /// Record the latest progress of the render.
+    fn update(&mut self, progress: &Progress) {
+        // Measuring between reports leaves out any rays from a checkpoint that we resumed from
+        if let Some(previous) = self.progress {
+            let elapsed = progress.elapsed.saturating_sub(previous.elapsed);
+            let rays = progress.stats.rays.saturating_sub(previous.stats.rays);
+            if !elapsed.is_zero() {
+                self.rays_per_second = Some(rays as f64 / elapsed.as_secs_f64());
+            }
+        }
+        self.progress = Some(*progress);
+    }
+
+    /// The lines of text to show in the HUD.
+    fn lines(&self) -> Vec<String> {
+        let Some(progress) = self.progress else {
+            return vec!["Starting".to_string()];
+        };
+
+        let mut lines = vec![];
+        let (done, total) = if progress.total_tiles > 0 {
+            lines.push(format!(
+                "Tile {}/{}",
+                progress.tiles_done, progress.total_tiles
+            ));
+            (progress.tiles_done, progress.total_tiles)
+        } else {
+            (progress.samples_done as u32, progress.total_samples as u32)
+        };
+        lines.push(format!(
+            "Sample {}/{}",
+            progress.samples_done, progress.total_samples
+        ));
+
+        // With adaptive sampling, this assumes that every pass takes as long as the ones so far,
+        // so the render usually finishes before the ETA
+        let eta = (done > 0).then(|| {
+            progress
+                .elapsed
+                .mul_f64(total.saturating_sub(done) as f64 / done as f64)
+        });
+        lines.push(format!(
+            "Elapsed {}  ETA {}",
+            format_clock(progress.elapsed),
+            eta.map_or("--".to_string(), format_clock)
+        ));
+
+        lines.push(match self.rays_per_second {
+            Some(rays_per_second) => format!("{:.2}M rays/s", rays_per_second / 1e6),
+            None => "-- rays/s".to_string(),
+        });
+
+        if progress.converged_pixels > 0 {
+            lines.push(format!(
+                "{:.1}% converged",
+                100. * progress.converged_pixels as f64 / progress.total_pixels as f64
+            ));
+        }
+
+        lines
+    }
+}
+
+/// Format a duration like a clock, as `m:ss` or `h:mm:ss`.
+fn format_clock(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes}:{seconds:02}")
+    }
+}
+
+/// Describe a pixel of the image for the pixel inspector in the preview window, with its raw
+/// linear colour and whatever a ray through its centre hits.
+fn describe_pixel(
+    image: &Rgb32FImage,
+    scene: &Bvh,
+    camera: &Camera,
+    scene_file: Option<&SceneFile>,
+    (x, y): (u32, u32),
+) -> String {
+    let format_vector = |vector: Vec3| format!("{:.4}, {:.4}, {:.4}", vector.x, vector.y, vector.z);
+    let colour = Colour::from(*image.get_pixel(x, y));
+
+    let mut lines = vec![
+        format!("Pixel ({x}, {y}):"),
+        format!(
+            "  Linear RGB: {:.6}, {:.6}, {:.6}",
+            colour.x, colour.y, colour.z
+        ),
+    ];
+
+    let Some(hit) = CentreHit::trace(scene, camera, image.dimensions(), (x, y)) else {
+        lines.push(
+            "  The centre of the pixel doesn't hit anything, so it shows the environment"
+                .to_string(),
+        );
+        return lines.join("\n");
+    };
+
+    // We can only describe objects and materials which came from a scene file
+    let object = scene_file
+        .zip(hit.object_id)
+        .and_then(|(scene_file, id)| scene_file.objects.get(id as usize));
+    let material_name = match object {
+        Some(
+            ObjectDescription::Sphere { material, .. }
+            | ObjectDescription::Triangle { material, .. },
+        ) => Some(material.as_str()),
+        Some(ObjectDescription::Mesh { material, .. }) => material.as_deref(),
+        None => None,
+    };
+    let material = scene_file
+        .zip(material_name)
+        .and_then(|(scene_file, name)| Some((name, scene_file.materials.get(name)?)));
+
+    lines.push(match (hit.object_id, object) {
+        (Some(id), Some(object)) => format!("  Object: {id}, {object:?}"),
+        (Some(id), None) => format!("  Object: {id}"),
+        (None, _) => "  Object: unknown".to_string(),
+    });
+    lines.push(match (material, object) {
+        (Some((name, material)), _) => format!("  Material: {name:?}, {material:?}"),
+        (None, Some(ObjectDescription::Mesh { .. })) => {
+            "  Material: from the mesh's MTL file".to_string()
+        }
+        (None, _) => "  Material: unknown".to_string(),
+    });
+    lines.push(format!("  Albedo: {}", format_vector(hit.albedo)));
+    lines.push(format!("  Depth: {:.4}", hit.depth));
+    lines.push(format!("  Position: {}", format_vector(hit.position)));
+    lines.push(format!("  Normal: {}", format_vector(hit.normal)));
+
+    lines.join("\n")
+}
+
+/// How much of the width and height of the screen the preview window can take up when it opens.
+const INITIAL_SCREEN_PROPORTION: f64 = 0.75;
+
+/// How big each pixel of the HUD font is in logical pixels of the window.
+const HUD_SCALE: f64 = 2.;
+
+/// How far the camera orbits when dragging the mouse across the whole height of the image, in
+/// degrees.
+const ORBIT_DEGREES_PER_HEIGHT: f64 = 180.;
+
+/// How much one step of the scroll wheel multiplies the distance from the camera to the point
+/// that it's looking at.
+const ZOOM_PER_SCROLL_STEP: f64 = 0.9;
+
+/// How many pixels of smooth scrolling, like on a touchpad, count as one step of the scroll
+/// wheel.
+const PIXELS_PER_SCROLL_STEP: f64 = 50.;
+
+/// How far each press of a movement key moves the camera, as a proportion of the distance from
+/// the camera to the point that it's looking at.
+const FLY_STEP: f64 = 0.05;
+
+/// Render the scene on a background thread, and show a live preview of the image in a window.
+///
+/// The camera can be moved with the mouse and keyboard in the window, which abandons the render
+/// and starts again from scratch with the new camera. The arguments get parsed again with the
+/// new camera added on the end.
+///
+/// Only the render with the original camera makes checkpoints and saves the image when it
+/// finishes, so that just looking around the scene doesn't overwrite anything. Renders with
+/// other cameras only get saved when asked, and never make checkpoints.
+///
+/// If the render fails, then the process exits with a non-zero status. If the window is closed
+/// before the render has finished, then the render is abandoned.
+fn run_with_preview(
+    event_loop: EventLoop<()>,
+    arguments: Vec<String>,
+    scene_file: Option<SceneFile>,
+    scene: Bvh,
+    renderer: Renderer,
+    environment: SharedEnvironment,
+    progress_bar: ProgressBar,
+) -> Result<()> {
+    let args = Args::parse_from(&arguments);
+    let camera_description = args.camera_description(scene_file.as_ref());
+    let scene = Arc::new(scene);
+    let framebuffer = renderer.framebuffer();
+    let stop_token = renderer.cancel_token();
+    let view_transform = args.view_transform();
+
+    // Start with the window as big as it can be within most of the screen, at the same aspect
+    // ratio as the image. It can be resized after that.
+    let screen_size = event_loop
+        .primary_monitor()
+        .map(|monitor| monitor.size().to_logical::<f64>(monitor.scale_factor()))
+        .map_or((1280., 720.), |size| {
+            (
+                size.width * INITIAL_SCREEN_PROPORTION,
+                size.height * INITIAL_SCREEN_PROPORTION,
+            )
+        });
+    let initial_scale = f64::max(
+        args.width as f64 / screen_size.0,
+        args.height as f64 / screen_size.1,
+    );
 
-    let event_loop = EventLoop::new().unwrap();
     let window = Arc::new(
         WindowBuilder::new()
-            .with_inner_size(LogicalSize::new(preview_width, preview_height))
-            .with_resizable(false)
+            .with_inner_size(LogicalSize::new(
+                args.width as f64 / initial_scale,
+                args.height as f64 / initial_scale,
+            ))
+            .with_resizable(true)
             .with_title("Raytracer")
             .build(&event_loop)?,
     );
     let context = softbuffer::Context::new(window.clone()).unwrap();
     let mut surface = softbuffer::Surface::new(&context, window.clone()).unwrap();
 
+    println!("Press Enter in the preview window to stop after the current pass and save the image");
+    println!(
+        "Drag with the left mouse button to orbit the camera, with the right or middle button to \
+         pan, and scroll to zoom. Use WASD to fly, Q and E to move down and up, and C to print \
+         the camera. Once the camera has moved, press O to save the image"
+    );
+    println!("Press H to show or hide the HUD, and I to click on pixels to inspect them");
     println!("Rendering scene...");
-    let start_time = Instant::now();
 
-    // Thread to actually do the raytracing
+    let viewpoint = Arc::new((
+        Mutex::new(Viewpoint {
+            camera: camera_description,
+            moves: 0,
+            cancel_token: stop_token.child(),
+            save_requested: false,
+        }),
+        Condvar::new(),
+    ));
+
+    let hud_status = Arc::new(Mutex::new(HudStatus::default()));
+    let (image_width, image_height) = (args.width, args.height);
+
+    // Thread to actually do the raytracing, which starts again whenever the camera moves
     thread::spawn({
-        let float_img = unsafe { &mut *float_img } as &mut Rgb32FImage;
+        let framebuffer = framebuffer.clone();
+        let progress_bar = progress_bar.clone();
+        let viewpoint = viewpoint.clone();
+        let hud_status = hud_status.clone();
+        let scene = scene.clone();
+        let window = window.clone();
+        let mut first_renderer = Some(renderer);
+
         move || {
-            for sample_idx in (0..args.samples).progress_with(progress_bar) {
-                float_img
-                    .par_enumerate_pixels_mut()
-                    .for_each(|(i, j, pixel)| {
-                        let mut rng = thread_rng();
-                        let sampled_colour = camera
-                            .get_ray(
-                                (i as f64 + offset_distribution.sample(&mut rng))
-                                    / args.width as f64,
-                                (j as f64 + offset_distribution.sample(&mut rng))
-                                    / args.height as f64,
-                            )
-                            .colour(&scene, args.bounces);
-                        let current_colour = Colour::from(*pixel);
-
-                        let avg_colour = (current_colour * sample_idx as f64 + sampled_colour)
-                            / (sample_idx + 1) as f64;
-
-                        *pixel = avg_colour.into();
+            let (viewpoint, camera_moved) = &*viewpoint;
+            let result = (|| loop {
+                let (camera_description, cancel_token, moves) = {
+                    let mut viewpoint = viewpoint.lock().unwrap();
+                    viewpoint.cancel_token = stop_token.child();
+                    (
+                        viewpoint.camera.clone(),
+                        viewpoint.cancel_token.clone(),
+                        viewpoint.moves,
+                    )
+                };
+
+                // The first render might be carrying on from a checkpoint, but any later ones
+                // have a different camera, so they start from scratch and don't touch the
+                // checkpoint
+                let original_camera = first_renderer.is_some();
+                let (args, renderer) = match first_renderer.take() {
+                    Some(renderer) => (args.clone(), renderer),
+                    None => {
+                        let arguments: Vec<String> = arguments
+                            .iter()
+                            .cloned()
+                            .chain(camera_description.to_arguments())
+                            .collect();
+                        let args = Args {
+                            checkpoint: None,
+                            resume: None,
+                            ..Args::parse_from(&arguments)
+                        };
+                        let renderer =
+                            build_renderer(&args, arguments, &environment, &progress_bar)?
+                                .with_framebuffer(framebuffer.clone());
+                        (args, renderer)
+                    }
+                };
+                let renderer = renderer
+                    .with_cancel_token(cancel_token)
+                    .with_progress_callback({
+                        let show_progress = show_progress(&progress_bar);
+                        let hud_status = hud_status.clone();
+                        let window = window.clone();
+                        move |progress| {
+                            show_progress(progress);
+                            hud_status.lock().unwrap().update(progress);
+                            window.request_redraw();
+                        }
                     });
-            }
+                *hud_status.lock().unwrap() = HudStatus::default();
+                window.request_redraw();
+                let camera =
+                    Camera::from(camera_description.to_camera_opts(args.width, args.height));
+
+                progress_bar.reset();
+                let (film, stats, time_taken) = render(&camera, &scene, &renderer, &progress_bar);
+                {
+                    let mut viewpoint = viewpoint.lock().unwrap();
+                    if viewpoint.moves != moves {
+                        continue;
+                    }
+
+                    // The image from the original camera gets saved straight away, like without
+                    // a preview window
+                    viewpoint.save_requested |= original_camera;
+                }
+                let image = finish(&args, &renderer, &film, &stats, time_taken);
+                if !original_camera {
+                    progress_bar.println(format!(
+                        "Press O in the preview window to save the image to {}",
+                        args.output
+                    ));
+                }
+
+                // Nothing is rendering now, so there's nothing for Enter to stop
+                renderer.cancel_token().cancel();
+
+                // Keep the window open with the finished image, save it whenever we're asked
+                // to, and render it again if the camera moves
+                loop {
+                    let mut viewpoint = viewpoint.lock().unwrap();
+                    while viewpoint.moves == moves && !viewpoint.save_requested {
+                        if stop_token.is_cancelled() {
+                            return Ok::<_, Report>(());
+                        }
+                        viewpoint = camera_moved
+                            .wait_timeout(viewpoint, Duration::from_millis(100))
+                            .unwrap()
+                            .0;
+                    }
+                    if viewpoint.moves != moves {
+                        break;
+                    }
 
-            let time_taken = start_time.elapsed();
-            println!("Rendering took {time_taken:?}");
+                    viewpoint.save_requested = false;
+                    drop(viewpoint);
+                    save(&args, &camera, &scene, &renderer, &film, &image)?;
+                }
+            })();
 
-            RgbImage::from(DynamicImage::from(float_img.to_owned()))
-                .save(&args.output)
-                .wrap_err("When trying to save image buffer")
-                .unwrap();
-            println!("Rendered to {}", args.output);
+            if let Err(error) = result {
+                eprintln!("Error: {error:?}");
+                process::exit(1);
+            }
         }
     });
 
-    // Redraw the window at 30 fps
+    // Redraw the window whenever the renderer publishes a new snapshot of the image
     thread::spawn({
         let window = window.clone();
-        move || loop {
-            thread::sleep(Duration::from_millis(100));
-            window.request_redraw();
+        let framebuffer = framebuffer.clone();
+        move || {
+            let mut drawn_generation = None;
+            loop {
+                thread::sleep(Duration::from_millis(100));
+                let generation = framebuffer.generation();
+                if drawn_generation != Some(generation) {
+                    drawn_generation = Some(generation);
+                    window.request_redraw();
+                }
+            }
         }
     });
 
+    // Move the camera and start rendering again
+    let move_camera = {
+        let viewpoint = viewpoint.clone();
+        move |f: &dyn Fn(&mut CameraDescription)| {
+            let (viewpoint, camera_moved) = &*viewpoint;
+            let mut viewpoint = viewpoint.lock().unwrap();
+            f(&mut viewpoint.camera);
+            viewpoint.moves += 1;
+            viewpoint.save_requested = false;
+            viewpoint.cancel_token.abandon();
+            camera_moved.notify_all();
+        }
+    };
+
+    // The mouse button being dragged and where the cursor was when we last moved the camera
+    let mut drag: Option<MouseButton> = None;
+    let mut cursor_position = PhysicalPosition::new(0., 0.);
+
+    // Whether to show the HUD, and whether clicking inspects pixels rather than moving the camera
+    let mut show_hud = true;
+    let mut inspecting = false;
+
+    // Where the image was last drawn in the window
+    let size = window.inner_size();
+    let mut viewport = Viewport::fit(
+        framebuffer.width(),
+        framebuffer.height(),
+        size.width,
+        size.height,
+    );
+
     event_loop.run(move |event, event_loop_window_target| {
         event_loop_window_target.set_control_flow(ControlFlow::Wait);
 
+        let Event::WindowEvent { window_id, event } = event else {
+            return;
+        };
+        if window_id != window.id() {
+            return;
+        }
+
         match event {
-            Event::WindowEvent {
-                window_id,
-                event: WindowEvent::RedrawRequested,
-            } if window_id == window.id() => {
-                let (width, height) = {
-                    let size = window.inner_size();
-                    (size.width, size.height)
+            WindowEvent::RedrawRequested => {
+                let size = window.inner_size();
+
+                // There's nothing to draw while the window is minimised
+                let (Some(width), Some(height)) =
+                    (NonZeroU32::new(size.width), NonZeroU32::new(size.height))
+                else {
+                    return;
                 };
-                surface
-                    .resize(
-                        NonZeroU32::new(width).unwrap(),
-                        NonZeroU32::new(height).unwrap(),
-                    )
-                    .unwrap();
+                surface.resize(width, height).unwrap();
 
                 let mut buffer = surface.buffer_mut().unwrap();
-                let float_img = unsafe { &*float_img as &Rgb32FImage };
-
-                let height = preview_height.floor() as u32;
-                let width = preview_width.floor() as u32;
-
-                for y in 0..height {
-                    for x in 0..width {
-                        let index = y * width + x;
-                        // TODO: Average out pixels in area?
-                        if let Some(pixel) = float_img.get_pixel_checked(
-                            (x as f64 * preview_scale_factor) as u32,
-                            (y as f64 * preview_scale_factor) as u32,
-                        ) {
-                            let [r, g, b] = pixel.0;
-
-                            let r = (r.clamp(0., 1.) * 255.).floor() as u32;
-                            let g = (g.clamp(0., 1.) * 255.).floor() as u32;
-                            let b = (b.clamp(0., 1.) * 255.).floor() as u32;
-
-                            buffer[index as usize] = (r << 16) | (g << 8) | b;
-                        } else {
-                            buffer[index as usize] = 0;
-                        }
+                viewport = draw_image(
+                    &framebuffer.snapshot(),
+                    &view_transform,
+                    &mut buffer,
+                    size.width,
+                    size.height,
+                );
+
+                if show_hud {
+                    let mut lines = hud_status.lock().unwrap().lines();
+                    if inspecting {
+                        lines.push("Inspecting: click a pixel".to_string());
                     }
+
+                    let scale = (HUD_SCALE * window.scale_factor()).round() as u32;
+                    draw_text(
+                        &mut buffer,
+                        size.width,
+                        size.height,
+                        (scale, scale),
+                        &lines,
+                        scale,
+                    );
                 }
 
                 buffer.present().unwrap();
             }
-            Event::WindowEvent {
-                window_id,
-                event: WindowEvent::CloseRequested,
-            } if window_id == window.id() => event_loop_window_target.exit(),
+            WindowEvent::Resized(_) => window.request_redraw(),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key,
+                        state: ElementState::Pressed,
+                        repeat,
+                        ..
+                    },
+                ..
+            } => match logical_key {
+                Key::Named(NamedKey::Enter) if !repeat => {
+                    let (viewpoint, _) = &*viewpoint;
+                    let cancel_token = &viewpoint.lock().unwrap().cancel_token;
+                    if !cancel_token.is_cancelled() {
+                        progress_bar.println("Stopping after the current pass");
+                        cancel_token.cancel();
+                    }
+                }
+                Key::Character(key) => {
+                    let key = key.to_lowercase();
+                    let step = |camera: &CameraDescription| FLY_STEP * camera.distance();
+
+                    match key.as_str() {
+                        "w" => move_camera(&|camera| camera.fly(step(camera), 0., 0.)),
+                        "s" => move_camera(&|camera| camera.fly(-step(camera), 0., 0.)),
+                        "a" => move_camera(&|camera| camera.fly(0., -step(camera), 0.)),
+                        "d" => move_camera(&|camera| camera.fly(0., step(camera), 0.)),
+                        "q" => move_camera(&|camera| camera.fly(0., 0., -step(camera))),
+                        "e" => move_camera(&|camera| camera.fly(0., 0., step(camera))),
+                        "h" if !repeat => {
+                            show_hud = !show_hud;
+                            window.request_redraw();
+                        }
+                        "i" if !repeat => {
+                            inspecting = !inspecting;
+                            progress_bar.println(if inspecting {
+                                "Click on a pixel to inspect it, or press I again to go back to \
+                                 moving the camera"
+                            } else {
+                                "Clicking and dragging moves the camera again"
+                            });
+                            window.request_redraw();
+                        }
+                        "o" if !repeat => {
+                            let (viewpoint, camera_moved) = &*viewpoint;
+                            let mut viewpoint = viewpoint.lock().unwrap();
+                            if !viewpoint.cancel_token.is_cancelled() {
+                                progress_bar.println(
+                                    "Saving the image once the render finishes, or press Enter \
+                                     to stop it now",
+                                );
+                            }
+                            viewpoint.save_requested = true;
+                            camera_moved.notify_all();
+                        }
+                        "c" if !repeat => {
+                            let (viewpoint, _) = &*viewpoint;
+                            let camera = &viewpoint.lock().unwrap().camera;
+                            progress_bar.println(format!(
+                                "The camera is:\n\n{}\n\n{}",
+                                camera.to_arguments().join(" "),
+                                camera.to_toml().trim_end()
+                            ));
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            },
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } if inspecting => {
+                if let Some(pixel) = viewport.image_pixel(cursor_position.x, cursor_position.y) {
+                    let (viewpoint, _) = &*viewpoint;
+                    let camera_description = viewpoint.lock().unwrap().camera.clone();
+                    let camera =
+                        Camera::from(camera_description.to_camera_opts(image_width, image_height));
+
+                    progress_bar.println(describe_pixel(
+                        &framebuffer.snapshot(),
+                        &scene,
+                        &camera,
+                        scene_file.as_ref(),
+                        pixel,
+                    ));
+                }
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                drag = match state {
+                    ElementState::Pressed => Some(button),
+                    ElementState::Released => None,
+                };
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let height = viewport.height as f64;
+                let (dx, dy) = (
+                    (position.x - cursor_position.x) / height,
+                    (position.y - cursor_position.y) / height,
+                );
+                cursor_position = position;
+
+                // Dragging moves the scene along with the cursor
+                match drag {
+                    Some(MouseButton::Left) => move_camera(&|camera| {
+                        camera.orbit(
+                            -dx * ORBIT_DEGREES_PER_HEIGHT,
+                            dy * ORBIT_DEGREES_PER_HEIGHT,
+                        )
+                    }),
+                    Some(MouseButton::Right | MouseButton::Middle) => {
+                        move_camera(&|camera| camera.pan(-dx, dy))
+                    }
+                    _ => {}
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let steps = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y as f64,
+                    MouseScrollDelta::PixelDelta(position) => position.y / PIXELS_PER_SCROLL_STEP,
+                };
+                if steps != 0. {
+                    move_camera(&|camera| camera.zoom(ZOOM_PER_SCROLL_STEP.powf(steps)));
+                }
+            }
+            WindowEvent::CloseRequested => event_loop_window_target.exit(),
             _ => {}
         };
     })?;
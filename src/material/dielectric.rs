@@ -4,23 +4,24 @@ use crate::{
     material::{reflect, reflectance, refract, Material, Reflection},
     object::Hit,
     ray::Ray,
+    sampler::Sampler,
+    texture::Texture,
     vector::Colour,
 };
-use rand::random;
 
 /// A transparent material like glass.
 #[derive(Clone, Copy, Debug, PartialEq)]
-pub struct Dielectric {
+pub struct Dielectric<T: Texture = Colour> {
     /// The colour tint of the material.
-    pub colour: Colour,
+    pub colour: T,
 
     /// The ratio of the refractive index of the external material to the refractive index of the
     /// internal material.
     pub refraction_ratio: f64,
 }
 
-impl Dielectric {
-    pub fn new(colour: Colour, refraction_ratio: f64) -> Self {
+impl<T: Texture> Dielectric<T> {
+    pub fn new(colour: T, refraction_ratio: f64) -> Self {
         Self {
             colour,
             refraction_ratio,
@@ -28,8 +29,13 @@ impl Dielectric {
     }
 }
 
-impl Material for Dielectric {
-    fn scatter(&self, incident_ray: &Ray, hit: &Hit) -> Option<Reflection> {
+impl<T: Texture> Material for Dielectric<T> {
+    fn scatter(
+        &self,
+        incident_ray: &Ray,
+        hit: &Hit,
+        sampler: &mut dyn Sampler,
+    ) -> Option<Reflection> {
         let ratio = if hit.front_face {
             self.refraction_ratio.recip()
         } else {
@@ -41,7 +47,7 @@ impl Material for Dielectric {
         let sin_theta = (1. - cos_theta * cos_theta).sqrt();
 
         let scatter_direction =
-            if (sin_theta * ratio > 1.) || (reflectance(cos_theta, ratio) > random()) {
+            if (sin_theta * ratio > 1.) || (reflectance(cos_theta, ratio) > sampler.next_1d()) {
                 reflect(incoming, hit.surface_normal)
             } else {
                 refract(incoming, hit.surface_normal, ratio)
@@ -49,7 +55,12 @@ impl Material for Dielectric {
 
         Some(Reflection {
             reflected_ray: Ray::new(hit.intersection_point, scatter_direction),
-            colour_attenuation: self.colour,
+            colour_attenuation: self.colour.value(hit.uv, hit.intersection_point),
+            pdf: None,
         })
     }
+
+    fn albedo(&self, hit: &Hit) -> Colour {
+        self.colour.value(hit.uv, hit.intersection_point)
+    }
 }
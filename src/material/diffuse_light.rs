@@ -0,0 +1,41 @@
+//! This module provides the [`DiffuseLight`] material.
+
+use crate::{
+    material::{Material, Reflection},
+    object::Hit,
+    ray::Ray,
+    sampler::Sampler,
+    vector::Colour,
+};
+
+/// A material which emits light equally in all directions and doesn't reflect anything.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DiffuseLight {
+    /// The colour of the emitted light. This can be brighter than 1 in each channel.
+    pub colour: Colour,
+}
+
+impl DiffuseLight {
+    pub fn new(colour: Colour) -> Self {
+        Self { colour }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _incident_ray: &Ray,
+        _hit: &Hit,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<Reflection> {
+        None
+    }
+
+    fn emitted(&self, _hit: &Hit) -> Colour {
+        self.colour
+    }
+
+    fn is_emissive(&self) -> bool {
+        !self.colour.is_zero()
+    }
+}
@@ -4,36 +4,63 @@ use crate::{
     material::{Material, Reflection},
     object::Hit,
     ray::Ray,
+    sampler::Sampler,
+    texture::Texture,
     vector::{Colour, Vec3},
 };
+use core::f64::consts::PI;
 
 /// A material with Lambertian diffuse reflection.
 #[derive(Clone, Copy, Debug, PartialEq)]
-pub struct Lambertian {
+pub struct Lambertian<T: Texture = Colour> {
     /// The colour of this material.
-    pub colour: Colour,
+    pub colour: T,
 }
 
-impl Lambertian {
-    pub fn new(colour: Colour) -> Self {
+impl<T: Texture> Lambertian<T> {
+    pub fn new(colour: T) -> Self {
         Self { colour }
     }
 }
 
-impl Material for Lambertian {
-    fn scatter(&self, _incident_ray: &Ray, hit: &Hit) -> Option<Reflection> {
-        let random_scatter_direction = hit.surface_normal + Vec3::random_unit_vector();
+impl<T: Texture> Material for Lambertian<T> {
+    fn scatter(
+        &self,
+        incident_ray: &Ray,
+        hit: &Hit,
+        sampler: &mut dyn Sampler,
+    ) -> Option<Reflection> {
+        // Adding a random unit vector to the normal gives a cosine-weighted distribution, which
+        // cancels out the cosine term of the BRDF
+        let random_scatter_direction =
+            hit.surface_normal + Vec3::unit_vector_from_sample(sampler.next_2d());
+        let reflected_ray = Ray::new(
+            hit.intersection_point,
+            if random_scatter_direction.is_zero() {
+                hit.surface_normal
+            } else {
+                random_scatter_direction
+            },
+        );
+        let pdf = self.scatter_pdf(incident_ray, hit, reflected_ray.direction);
 
         Some(Reflection {
-            reflected_ray: Ray::new(
-                hit.intersection_point,
-                if random_scatter_direction.is_zero() {
-                    hit.surface_normal
-                } else {
-                    random_scatter_direction
-                },
-            ),
-            colour_attenuation: self.colour,
+            reflected_ray,
+            colour_attenuation: self.colour.value(hit.uv, hit.intersection_point),
+            pdf: Some(pdf),
         })
     }
+
+    fn eval_brdf(&self, _incident_ray: &Ray, hit: &Hit, direction: Vec3) -> Colour {
+        let cos = hit.surface_normal.dot(direction.normalise()).max(0.);
+        self.colour.value(hit.uv, hit.intersection_point) * (cos / PI)
+    }
+
+    fn scatter_pdf(&self, _incident_ray: &Ray, hit: &Hit, direction: Vec3) -> f64 {
+        hit.surface_normal.dot(direction.normalise()).max(0.) / PI
+    }
+
+    fn albedo(&self, hit: &Hit) -> Colour {
+        self.colour.value(hit.uv, hit.intersection_point)
+    }
 }
@@ -4,22 +4,24 @@ use crate::{
     material::{reflect, Material, Reflection},
     object::Hit,
     ray::Ray,
+    sampler::Sampler,
+    texture::Texture,
     vector::{Colour, Vec3},
 };
 
 /// A reflective metal.
 #[derive(Clone, Copy, Debug, PartialEq)]
-pub struct Metal {
+pub struct Metal<T: Texture = Colour> {
     /// The colour of this material.
-    pub colour: Colour,
+    pub colour: T,
 
     /// The fuzziness of this material. It should be in `[0, 1]` and will be clamped down to this
     /// range.
     pub fuzz: f64,
 }
 
-impl Metal {
-    pub fn new(colour: Colour, fuzz: f64) -> Self {
+impl<T: Texture> Metal<T> {
+    pub fn new(colour: T, fuzz: f64) -> Self {
         Self {
             colour,
             fuzz: fuzz.clamp(0., 1.),
@@ -27,19 +29,30 @@ impl Metal {
     }
 }
 
-impl Material for Metal {
-    fn scatter(&self, incident_ray: &Ray, hit: &Hit) -> Option<Reflection> {
+impl<T: Texture> Material for Metal<T> {
+    fn scatter(
+        &self,
+        incident_ray: &Ray,
+        hit: &Hit,
+        sampler: &mut dyn Sampler,
+    ) -> Option<Reflection> {
         let reflection_direction = reflect(incident_ray.direction, hit.surface_normal)
-            + self.fuzz * Vec3::random_unit_vector();
+            + self.fuzz * Vec3::unit_vector_from_sample(sampler.next_2d());
         let reflected_ray = Ray::new(hit.intersection_point, reflection_direction);
 
         if reflected_ray.direction.dot(hit.surface_normal) > 0. {
             Some(Reflection {
                 reflected_ray,
-                colour_attenuation: self.colour,
+                colour_attenuation: self.colour.value(hit.uv, hit.intersection_point),
+                // Fuzzy reflections don't have a simple BRDF, so we treat them as specular
+                pdf: None,
             })
         } else {
             None
         }
     }
+
+    fn albedo(&self, hit: &Hit) -> Colour {
+        self.colour.value(hit.uv, hit.intersection_point)
+    }
 }
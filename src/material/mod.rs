@@ -1,26 +1,134 @@
 //! This module deals with materials.
 
 mod dielectric;
+mod diffuse_light;
 mod lambertian;
 mod metal;
 
-use crate::{object::Hit, ray::Ray, vector::Vec3};
+use crate::{
+    object::Hit,
+    ray::Ray,
+    sampler::Sampler,
+    vector::{Colour, Vec3},
+};
+use std::sync::Arc;
 
-pub use self::{dielectric::Dielectric, lambertian::Lambertian, metal::Metal};
+pub use self::{
+    dielectric::Dielectric, diffuse_light::DiffuseLight, lambertian::Lambertian, metal::Metal,
+};
 
 /// A trait to represent a material.
-pub trait Material {
-    fn scatter(&self, incident_ray: &Ray, hit: &Hit) -> Option<Reflection>;
+///
+/// Materials must be [`Send`] and [`Sync`] so that the scene can be rendered on several threads.
+pub trait Material: Send + Sync {
+    /// Randomly scatter the incident ray off the surface, or return `None` if the ray gets
+    /// absorbed.
+    fn scatter(
+        &self,
+        incident_ray: &Ray,
+        hit: &Hit,
+        sampler: &mut dyn Sampler,
+    ) -> Option<Reflection>;
+
+    /// The light emitted by this material at the given hit. Most materials don't emit any light,
+    /// so this defaults to black.
+    fn emitted(&self, _hit: &Hit) -> Colour {
+        Colour::ZERO
+    }
+
+    /// Does this material emit any light? Objects made of emissive materials get sampled directly
+    /// as lights.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// The colour of this material at the given hit, ignoring lighting. This is used for the
+    /// albedo pass, which helps denoisers tell texture detail apart from noise. Materials which
+    /// don't reflect anything default to black.
+    fn albedo(&self, _hit: &Hit) -> Colour {
+        Colour::ZERO
+    }
+
+    /// Evaluate the BRDF for light arriving from the given direction and leaving back along the
+    /// incident ray, multiplied by the cosine of the angle between the direction and the surface
+    /// normal.
+    ///
+    /// Materials which only reflect specularly can't be evaluated like this, so this defaults to
+    /// black.
+    fn eval_brdf(&self, _incident_ray: &Ray, _hit: &Hit, _direction: Vec3) -> Colour {
+        Colour::ZERO
+    }
+
+    /// The probability density, with respect to solid angle, of [`Material::scatter`] picking
+    /// the given direction. This defaults to zero for materials which only reflect specularly.
+    fn scatter_pdf(&self, _incident_ray: &Ray, _hit: &Hit, _direction: Vec3) -> f64 {
+        0.
+    }
+
+    /// The address of this material, which is the same for every object that shares it. The
+    /// material ID pass uses this to tell materials apart, so anything which wraps a material
+    /// should forward this to the material inside.
+    fn address(&self) -> *const () {
+        self as *const Self as *const ()
+    }
 }
 
+// This lets objects share materials, and lets us choose materials at runtime with
+// `Arc<dyn Material>`.
+impl<M> Material for Arc<M>
+where
+    M: Material + ?Sized,
+{
+    fn scatter(
+        &self,
+        incident_ray: &Ray,
+        hit: &Hit,
+        sampler: &mut dyn Sampler,
+    ) -> Option<Reflection> {
+        (**self).scatter(incident_ray, hit, sampler)
+    }
+
+    fn emitted(&self, hit: &Hit) -> Colour {
+        (**self).emitted(hit)
+    }
+
+    fn is_emissive(&self) -> bool {
+        (**self).is_emissive()
+    }
+
+    fn albedo(&self, hit: &Hit) -> Colour {
+        (**self).albedo(hit)
+    }
+
+    fn eval_brdf(&self, incident_ray: &Ray, hit: &Hit, direction: Vec3) -> Colour {
+        (**self).eval_brdf(incident_ray, hit, direction)
+    }
+
+    fn scatter_pdf(&self, incident_ray: &Ray, hit: &Hit, direction: Vec3) -> f64 {
+        (**self).scatter_pdf(incident_ray, hit, direction)
+    }
+
+    fn address(&self) -> *const () {
+        (**self).address()
+    }
+}
+
+/// A material which can be shared between several objects and chosen at runtime.
+pub type SharedMaterial = Arc<dyn Material + Sync + Send>;
+
 /// Information about the reflection.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Reflection {
     /// The newly reflected ray.
     pub reflected_ray: Ray,
 
-    /// How the colour gets attenuated by the reflection.
+    /// How the colour gets attenuated by the reflection. This is the BRDF times the cosine term,
+    /// divided by the probability density of picking the reflected ray.
     pub colour_attenuation: Vec3,
+
+    /// The probability density, with respect to solid angle, of picking the reflected ray. This
+    /// is `None` for specular reflections, which can't be combined with light sampling.
+    pub pdf: Option<f64>,
 }
 
 /// Perfectly reflect a ray from a surface, given the normal vector of the tangent plane.
@@ -0,0 +1,121 @@
+//! This module provides the [`Aabb`] type.
+
+use crate::{
+    ray::Ray,
+    vector::{Point, Vec3},
+};
+
+/// An axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    /// The corner of the box with the smallest coordinates.
+    pub min: Point,
+
+    /// The corner of the box with the largest coordinates.
+    pub max: Point,
+}
+
+impl Aabb {
+    /// An empty bounding box, which contains nothing. Taking the union of this box with any other
+    /// box will return the other box.
+    pub const EMPTY: Self = Self {
+        min: Vec3::splat(f64::INFINITY),
+        max: Vec3::splat(f64::NEG_INFINITY),
+    };
+
+    /// Create a new bounding box from two opposite corners. The corners don't need to be given in
+    /// any particular order.
+    pub fn new(a: Point, b: Point) -> Self {
+        Self {
+            min: a.min_elementwise(b),
+            max: a.max_elementwise(b),
+        }
+    }
+
+    /// Get the smallest bounding box which contains both this box and the other one.
+    #[inline]
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            min: self.min.min_elementwise(other.min),
+            max: self.max.max_elementwise(other.max),
+        }
+    }
+
+    /// Get the smallest bounding box which contains both this box and the given point.
+    #[inline]
+    pub fn union_point(self, point: Point) -> Self {
+        Self {
+            min: self.min.min_elementwise(point),
+            max: self.max.max_elementwise(point),
+        }
+    }
+
+    /// Does this box contain nothing?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    /// The point in the middle of this box.
+    #[inline]
+    pub fn centroid(&self) -> Point {
+        (self.min + self.max) / 2.
+    }
+
+    /// The index of the axis along which this box is longest. See [`Vec3::axis`].
+    pub fn longest_axis(&self) -> usize {
+        let size = self.max - self.min;
+        if size.x >= size.y && size.x >= size.z {
+            0
+        } else if size.y >= size.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// The total surface area of this box, or 0 if the box is empty.
+    pub fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.;
+        }
+
+        let size = self.max - self.min;
+        2. * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    /// Does the given ray hit this box with a parameter `t` in the given bounds? If so, return the
+    /// value of `t` where the ray enters the box (clamped to the bounds).
+    ///
+    /// This uses the slab method, and `inverse_direction` should be the elementwise reciprocal of
+    /// the ray's direction, which is taken as a parameter so that it only needs to be computed
+    /// once per ray.
+    #[inline]
+    pub fn hit(&self, ray: &Ray, inverse_direction: Vec3, bounds: (f64, f64)) -> Option<f64> {
+        let (mut t_min, mut t_max) = bounds;
+
+        for axis in 0..3 {
+            let origin = ray.origin.axis(axis);
+            let inv = inverse_direction.axis(axis);
+
+            let t0 = (self.min.axis(axis) - origin) * inv;
+            let t1 = (self.max.axis(axis) - origin) * inv;
+            let (t0, t1) = if inv < 0. { (t1, t0) } else { (t0, t1) };
+
+            // These comparisons are written this way round so that NaNs (from 0 * infinity) don't
+            // shrink the interval.
+            if t0 > t_min {
+                t_min = t0;
+            }
+            if t1 < t_max {
+                t_max = t1;
+            }
+
+            if t_max < t_min {
+                return None;
+            }
+        }
+
+        Some(t_min)
+    }
+}
@@ -0,0 +1,555 @@
+//! This module provides the [`Bvh`] type.
+
+use super::{Aabb, Hit, Object, Scene};
+use crate::{ray::Ray, vector::Point};
+
+/// How many buckets should we use when estimating the cost of splitting a node with the surface
+/// area heuristic?
+const SAH_BUCKET_COUNT: usize = 12;
+
+/// The maximum number of objects that we're happy to put in a single leaf node.
+const MAX_OBJECTS_IN_LEAF: usize = 4;
+
+/// The relative cost of testing a ray against a bounding box, compared to testing a ray against an
+/// object.
+const TRAVERSAL_COST: f64 = 0.125;
+
+/// The deepest that the tree can get, which is also the size of the stack used to traverse it.
+/// Once the surface area heuristic gets near this depth, we split the objects evenly instead.
+const MAX_DEPTH: usize = 64;
+
+/// A bounding volume hierarchy, which is a tree of axis-aligned bounding boxes that lets us avoid
+/// testing a ray against every object in the scene.
+///
+/// The tree is stored flattened in depth-first order, so the left child of an interior node is
+/// always the node straight after it.
+pub struct Bvh {
+    /// All the objects in the tree, ordered so that each leaf node refers to a contiguous range.
+    objects: Scene,
+
+    /// The flattened nodes of the tree. The root is at index 0.
+    nodes: Vec<Node>,
+}
+
+/// A single node in a [`Bvh`].
+#[derive(Clone, Copy, Debug)]
+enum Node {
+    /// A leaf node, containing some objects.
+    Leaf {
+        /// The bounding box of all the objects in this leaf.
+        bounding_box: Aabb,
+
+        /// The index of the first object in this leaf.
+        first_object: usize,
+
+        /// The number of objects in this leaf.
+        object_count: usize,
+    },
+
+    /// An interior node with two children. The left child is always the next node along.
+    Interior {
+        /// The bounding box of both children.
+        bounding_box: Aabb,
+
+        /// The index of the right child.
+        right_child: usize,
+
+        /// The axis that the objects were split along. See [`Vec3::axis`](crate::vector::Vec3::axis).
+        split_axis: usize,
+    },
+}
+
+/// Information about an object which we need when building the tree.
+struct BuildInfo {
+    /// The index of the object in the original list.
+    index: usize,
+
+    /// The bounding box of the object.
+    bounding_box: Aabb,
+
+    /// The centre of the bounding box of the object.
+    centroid: Point,
+}
+
+impl Bvh {
+    /// Build a new BVH from all the objects in the scene.
+    pub fn new(objects: Scene) -> Self {
+        let mut build_info: Vec<BuildInfo> = objects
+            .iter()
+            .enumerate()
+            .map(|(index, object)| {
+                let bounding_box = object.bounding_box();
+                BuildInfo {
+                    index,
+                    bounding_box,
+                    centroid: bounding_box.centroid(),
+                }
+            })
+            .collect();
+
+        let mut nodes = Vec::with_capacity(2 * objects.len());
+        if !build_info.is_empty() {
+            build_recursive(&mut build_info, 0, 0, &mut nodes);
+        }
+
+        // Reorder the objects to match the order that the leaves expect
+        let mut objects: Vec<Option<_>> = objects.into_iter().map(Some).collect();
+        let objects = build_info
+            .iter()
+            .map(|info| {
+                objects[info.index]
+                    .take()
+                    .expect("Every object should appear exactly once in the BVH")
+            })
+            .collect();
+
+        Self { objects, nodes }
+    }
+
+    /// How many objects are in this BVH?
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Does this BVH contain no objects?
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+}
+
+impl From<Scene> for Bvh {
+    fn from(value: Scene) -> Self {
+        Self::new(value)
+    }
+}
+
+/// Recursively build the subtree containing the given objects, pushing the nodes onto the list in
+/// depth-first order. `offset` is the index of the first object of `build_info` in the full list,
+/// and `depth` is how many interior nodes are above this subtree.
+fn build_recursive(
+    build_info: &mut [BuildInfo],
+    offset: usize,
+    depth: usize,
+    nodes: &mut Vec<Node>,
+) {
+    let bounding_box = build_info
+        .iter()
+        .fold(Aabb::EMPTY, |acc, info| acc.union(info.bounding_box));
+
+    let make_leaf = |nodes: &mut Vec<Node>| {
+        nodes.push(Node::Leaf {
+            bounding_box,
+            first_object: offset,
+            object_count: build_info.len(),
+        })
+    };
+
+    if build_info.len() == 1 {
+        make_leaf(nodes);
+        return;
+    }
+
+    let centroid_bounds = build_info
+        .iter()
+        .fold(Aabb::EMPTY, |acc, info| acc.union_point(info.centroid));
+    let split_axis = centroid_bounds.longest_axis();
+    let axis_min = centroid_bounds.min.axis(split_axis);
+    let axis_max = centroid_bounds.max.axis(split_axis);
+
+    // If all the centroids are in the same place, then we can't usefully split them
+    if axis_max <= axis_min || !(axis_max - axis_min).is_finite() {
+        if build_info.len() <= MAX_OBJECTS_IN_LEAF {
+            make_leaf(nodes);
+            return;
+        }
+
+        let mid = build_info.len() / 2;
+        build_interior(
+            build_info,
+            offset,
+            depth,
+            mid,
+            bounding_box,
+            split_axis,
+            nodes,
+        );
+        return;
+    }
+
+    // Splitting evenly from here on needs one level for every halving, so if that would only just
+    // fit then we have to start now
+    let even_split_depth = build_info.len().next_power_of_two().ilog2() as usize;
+    if depth + even_split_depth >= MAX_DEPTH {
+        let mid = split_evenly(build_info, split_axis);
+        build_interior(
+            build_info,
+            offset,
+            depth,
+            mid,
+            bounding_box,
+            split_axis,
+            nodes,
+        );
+        return;
+    }
+
+    let bucket_index = |centroid: Point| -> usize {
+        let proportion = (centroid.axis(split_axis) - axis_min) / (axis_max - axis_min);
+        ((proportion * SAH_BUCKET_COUNT as f64) as usize).min(SAH_BUCKET_COUNT - 1)
+    };
+
+    let mut bucket_counts = [0usize; SAH_BUCKET_COUNT];
+    let mut bucket_boxes = [Aabb::EMPTY; SAH_BUCKET_COUNT];
+    for info in build_info.iter() {
+        let index = bucket_index(info.centroid);
+        bucket_counts[index] += 1;
+        bucket_boxes[index] = bucket_boxes[index].union(info.bounding_box);
+    }
+
+    // Estimate the cost of splitting after each bucket with the surface area heuristic
+    let parent_area = bounding_box.surface_area();
+    let (best_split, best_cost) = (0..SAH_BUCKET_COUNT - 1)
+        .map(|split| {
+            let (left_box, left_count) = bucket_boxes[..=split]
+                .iter()
+                .zip(&bucket_counts[..=split])
+                .fold((Aabb::EMPTY, 0), |(bb, n), (b, c)| (bb.union(*b), n + c));
+            let (right_box, right_count) = bucket_boxes[split + 1..]
+                .iter()
+                .zip(&bucket_counts[split + 1..])
+                .fold((Aabb::EMPTY, 0), |(bb, n), (b, c)| (bb.union(*b), n + c));
+
+            let cost = TRAVERSAL_COST
+                + (left_count as f64 * left_box.surface_area()
+                    + right_count as f64 * right_box.surface_area())
+                    / parent_area;
+            (split, cost)
+        })
+        .fold(
+            (0, f64::INFINITY),
+            |(best_split, best_cost), (split, cost)| {
+                if cost < best_cost {
+                    (split, cost)
+                } else {
+                    (best_split, best_cost)
+                }
+            },
+        );
+
+    let leaf_cost = build_info.len() as f64;
+    if build_info.len() <= MAX_OBJECTS_IN_LEAF && (best_cost >= leaf_cost || !best_cost.is_finite())
+    {
+        make_leaf(nodes);
+        return;
+    }
+
+    // Partition the objects so that everything in the left buckets comes first
+    let mut mid = 0;
+    for i in 0..build_info.len() {
+        if bucket_index(build_info[i].centroid) <= best_split {
+            build_info.swap(i, mid);
+            mid += 1;
+        }
+    }
+
+    // The SAH might not have managed to separate anything if the costs were all infinite, so we
+    // fall back to an even split
+    if mid == 0 || mid == build_info.len() {
+        mid = split_evenly(build_info, split_axis);
+    }
+
+    build_interior(
+        build_info,
+        offset,
+        depth,
+        mid,
+        bounding_box,
+        split_axis,
+        nodes,
+    );
+}
+
+/// Sort the objects along the given axis, and return the index which splits them in half.
+fn split_evenly(build_info: &mut [BuildInfo], split_axis: usize) -> usize {
+    build_info.sort_unstable_by(|a, b| {
+        a.centroid
+            .axis(split_axis)
+            .total_cmp(&b.centroid.axis(split_axis))
+    });
+    build_info.len() / 2
+}
+
+/// Build an interior node whose children contain `build_info[..mid]` and `build_info[mid..]`.
+fn build_interior(
+    build_info: &mut [BuildInfo],
+    offset: usize,
+    depth: usize,
+    mid: usize,
+    bounding_box: Aabb,
+    split_axis: usize,
+    nodes: &mut Vec<Node>,
+) {
+    let node_index = nodes.len();
+    nodes.push(Node::Interior {
+        bounding_box,
+        right_child: 0,
+        split_axis,
+    });
+
+    let (left, right) = build_info.split_at_mut(mid);
+    build_recursive(left, offset, depth + 1, nodes);
+
+    let right_index = nodes.len();
+    if let Node::Interior { right_child, .. } = &mut nodes[node_index] {
+        *right_child = right_index;
+    }
+    build_recursive(right, offset + mid, depth + 1, nodes);
+}
+
+impl Object for Bvh {
+    fn hit(&self, ray: &Ray, bounds: (f64, f64)) -> Option<Hit<'_>> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let inverse_direction = ray.direction.map(f64::recip);
+        let (lower, mut upper) = bounds;
+        let mut closest_hit = None;
+
+        // Keep the nodes still to visit in an array rather than a vec, since this runs for every ray
+        let mut stack = [0; MAX_DEPTH];
+        let mut stack_len = 0;
+        let mut current = 0;
+
+        loop {
+            match self.nodes[current] {
+                Node::Leaf {
+                    bounding_box,
+                    first_object,
+                    object_count,
+                } => {
+                    if bounding_box
+                        .hit(ray, inverse_direction, (lower, upper))
+                        .is_some()
+                    {
+                        for object in &self.objects[first_object..first_object + object_count] {
+                            if let Some(hit) = object.hit(ray, (lower, upper)) {
+                                upper = hit.t;
+                                closest_hit = Some(hit);
+                            }
+                        }
+                    }
+                }
+                Node::Interior {
+                    bounding_box,
+                    right_child,
+                    split_axis,
+                } => {
+                    if bounding_box
+                        .hit(ray, inverse_direction, (lower, upper))
+                        .is_some()
+                    {
+                        // Visit the nearer child first so that we can shrink the bounds sooner
+                        let left_child = current + 1;
+                        let (near_child, far_child) = if inverse_direction.axis(split_axis) < 0. {
+                            (right_child, left_child)
+                        } else {
+                            (left_child, right_child)
+                        };
+                        debug_assert!(stack_len < MAX_DEPTH, "The BVH is too deep");
+                        stack[stack_len] = far_child;
+                        stack_len += 1;
+                        current = near_child;
+                        continue;
+                    }
+                }
+            }
+
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            current = stack[stack_len];
+        }
+
+        closest_hit
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        match self.nodes.first() {
+            Some(Node::Leaf { bounding_box, .. } | Node::Interior { bounding_box, .. }) => {
+                *bounding_box
+            }
+            None => Aabb::EMPTY,
+        }
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Object>) {
+        self.objects.collect_lights(lights);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::Lambertian,
+        object::{identify_objects, random_scene, Sphere},
+        random::{seeded_rng, RenderRng},
+        vector::Vec3,
+    };
+    use rand::Rng;
+
+    const BOUNDS: (f64, f64) = (1e-5, f64::INFINITY);
+
+    /// Pick a ray starting somewhere around the objects in [`random_scene`], pointing in a random
+    /// direction.
+    fn random_ray(rng: &mut RenderRng) -> Ray {
+        let origin = Point::new(
+            rng.gen_range(-15.0..15.0),
+            rng.gen_range(0.0..5.0),
+            rng.gen_range(-15.0..15.0),
+        );
+        let direction = Vec3::unit_vector_from_sample((rng.gen(), rng.gen()));
+        Ray::new(origin, direction)
+    }
+
+    /// Check that the tree and a linear search over the same objects agree on which object the
+    /// ray hits first, and where.
+    fn assert_same_hit(bvh: &Bvh, scene: &Scene, ray: &Ray) {
+        let expected = scene.hit(ray, BOUNDS);
+        let actual = bvh.hit(ray, BOUNDS);
+        match (expected, actual) {
+            (Some(expected), Some(actual)) => {
+                assert_eq!(actual.t, expected.t, "for {ray:?}");
+                assert_eq!(actual.object_id, expected.object_id, "for {ray:?}");
+            }
+            (None, None) => {}
+            (expected, actual) => panic!(
+                "for {ray:?}, a linear search hit object {:?} but the tree hit {:?}",
+                expected.map(|hit| hit.object_id),
+                actual.map(|hit| hit.object_id),
+            ),
+        }
+    }
+
+    /// The most interior nodes on the way from the root to any leaf.
+    fn depth(bvh: &Bvh, node: usize) -> usize {
+        match bvh.nodes[node] {
+            Node::Leaf { .. } => 0,
+            Node::Interior { right_child, .. } => {
+                1 + depth(bvh, node + 1).max(depth(bvh, right_child))
+            }
+        }
+    }
+
+    /// `count` spheres which all have their centre at the origin.
+    fn concentric_spheres(count: usize) -> Scene {
+        (1..=count)
+            .map(|i| {
+                Box::new(Sphere::new(
+                    Point::new(0., 0., 0.),
+                    i as f64,
+                    Lambertian::new(Vec3::new(0.5, 0.5, 0.5)),
+                )) as Box<dyn Object + Sync + Send>
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_tree_finds_the_same_hits_as_a_linear_search() {
+        for seed in 0..3 {
+            let scene = identify_objects(random_scene(seed));
+            let bvh = Bvh::new(identify_objects(random_scene(seed)));
+            assert_eq!(bvh.len(), scene.len());
+            assert_eq!(bvh.bounding_box(), scene.bounding_box());
+
+            let mut rng = seeded_rng(seed);
+            let mut hits = 0;
+            for _ in 0..2000 {
+                let ray = random_ray(&mut rng);
+                hits += scene.hit(&ray, BOUNDS).is_some() as usize;
+                assert_same_hit(&bvh, &scene, &ray);
+            }
+            // Make sure the rays actually test something.
+            assert!(hits > 500, "only {hits} rays hit anything");
+        }
+    }
+
+    #[test]
+    fn the_tree_never_gets_too_deep() {
+        // The gaps between these spheres keep growing, so the surface area heuristic only splits
+        // off a few spheres at each level, and would make a tree about 90 levels deep
+        let spheres = || -> Scene {
+            (0..600)
+                .map(|i| {
+                    Box::new(Sphere::new(
+                        Point::new(1.5f64.powi(i), 0., 0.),
+                        0.1,
+                        Lambertian::new(Vec3::new(0.5, 0.5, 0.5)),
+                    )) as Box<dyn Object + Sync + Send>
+                })
+                .collect()
+        };
+        let scene = identify_objects(spheres());
+        let bvh = Bvh::new(identify_objects(spheres()));
+        assert!(depth(&bvh, 0) <= MAX_DEPTH, "{}", depth(&bvh, 0));
+
+        // The far spheres are too far away to hit precisely, so only aim at the near ones
+        let mut rng = seeded_rng(3);
+        for i in 0..30 {
+            let ray = Ray::new(
+                Point::new(0., 0., -1.),
+                Vec3::new(1.5f64.powi(i), 0., 1.)
+                    + Vec3::unit_vector_from_sample((rng.gen(), rng.gen())) * 0.05,
+            );
+            assert_same_hit(&bvh, &scene, &ray);
+        }
+    }
+
+    #[test]
+    fn an_empty_tree_is_never_hit() {
+        let bvh = Bvh::new(Vec::new());
+        assert!(bvh.is_empty());
+        assert_eq!(bvh.bounding_box(), Aabb::EMPTY);
+
+        let mut rng = seeded_rng(0);
+        for _ in 0..100 {
+            assert!(bvh.hit(&random_ray(&mut rng), BOUNDS).is_none());
+        }
+    }
+
+    #[test]
+    fn a_tree_with_one_object_finds_the_same_hits_as_the_object() {
+        let scene = identify_objects(concentric_spheres(1));
+        let bvh = Bvh::new(identify_objects(concentric_spheres(1)));
+        assert_eq!(bvh.len(), 1);
+        assert_eq!(bvh.bounding_box(), scene.bounding_box());
+
+        let mut rng = seeded_rng(1);
+        for _ in 0..500 {
+            let ray = random_ray(&mut rng);
+            assert_same_hit(&bvh, &scene, &ray);
+            // Rays from inside the sphere hit it too.
+            let ray = Ray::new(Point::new(0., 0.5, 0.), ray.direction);
+            assert_same_hit(&bvh, &scene, &ray);
+        }
+    }
+
+    #[test]
+    fn a_tree_can_be_built_when_every_centroid_is_in_the_same_place() {
+        let count = 4 * MAX_OBJECTS_IN_LEAF + 1;
+        let scene = identify_objects(concentric_spheres(count));
+        let bvh = Bvh::new(identify_objects(concentric_spheres(count)));
+        assert_eq!(bvh.len(), count);
+        assert_eq!(bvh.bounding_box(), scene.bounding_box());
+
+        let mut rng = seeded_rng(2);
+        for _ in 0..500 {
+            let ray = random_ray(&mut rng);
+            assert_same_hit(&bvh, &scene, &ray);
+            let ray = Ray::new(Point::new(0., 0.5, 0.), ray.direction);
+            assert_same_hit(&bvh, &scene, &ray);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! This module provides the [`Identified`] type.
+
+use super::{Aabb, Hit, Object, Scene};
+use crate::{
+    ray::Ray,
+    sampler::Sampler,
+    vector::{Point, Vec3},
+};
+
+/// An object with an ID, which gets put in every [`Hit`] on the object so that the object ID pass
+/// can tell objects apart.
+///
+/// The ID covers the whole object, so every triangle of a mesh gets the same ID.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Identified<O: Object> {
+    /// The ID of the object.
+    id: u32,
+
+    /// The object itself.
+    object: O,
+}
+
+impl<O: Object> Identified<O> {
+    /// Give the object the given ID.
+    pub fn new(id: u32, object: O) -> Self {
+        Self { id, object }
+    }
+}
+
+impl<O: Object> Object for Identified<O> {
+    fn hit(&self, ray: &Ray, bounds: (f64, f64)) -> Option<Hit<'_>> {
+        self.object.hit(ray, bounds).map(|hit| Hit {
+            object_id: Some(self.id),
+            ..hit
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.object.bounding_box()
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Object>) {
+        self.object.collect_lights(lights);
+    }
+
+    fn sample_direction(&self, origin: Point, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        self.object.sample_direction(origin, sampler)
+    }
+
+    fn direction_pdf(&self, origin: Point, direction: Vec3) -> f64 {
+        self.object.direction_pdf(origin, direction)
+    }
+}
+
+/// Give every object in the scene its index in the scene as its ID. This needs to happen before
+/// the scene gets put into a [`Bvh`](super::Bvh), which reorders the objects.
+pub fn identify_objects(scene: Scene) -> Scene {
+    scene
+        .into_iter()
+        .enumerate()
+        .map(|(index, object)| -> Box<dyn Object + Sync + Send> {
+            Box::new(Identified::new(index as u32, object))
+        })
+        .collect()
+}
@@ -0,0 +1,92 @@
+//! This module provides the [`Lights`] type.
+
+use super::Object;
+use crate::{
+    environment::Environment,
+    sampler::Sampler,
+    vector::{Point, Vec3},
+};
+
+/// All the light sources in a scene, so that the renderer can sample light from them directly
+/// rather than waiting for rays to happen to hit them.
+///
+/// The light sources are the objects which emit light, and the environment if it supports
+/// sampling. When sampling, each light source is equally likely to be picked.
+pub struct Lights<'a> {
+    /// The emissive objects.
+    lights: Vec<&'a dyn Object>,
+
+    /// The environment surrounding the scene.
+    environment: &'a dyn Environment,
+}
+
+impl<'a> Lights<'a> {
+    /// Find all the lights in the given scene.
+    pub fn new(scene: &'a (impl Object + ?Sized), environment: &'a dyn Environment) -> Self {
+        let mut lights = Vec::new();
+        scene.collect_lights(&mut lights);
+        Self {
+            lights,
+            environment,
+        }
+    }
+
+    /// The environment surrounding the scene.
+    pub fn environment(&self) -> &'a dyn Environment {
+        self.environment
+    }
+
+    /// How many light sources can be sampled?
+    pub fn len(&self) -> usize {
+        self.lights.len() + usize::from(self.environment.supports_sampling())
+    }
+
+    /// Are there no light sources which can be sampled?
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Pick a random light source and a random direction from the given origin towards it.
+    /// This returns the direction along with the object which was picked, or `None` for the
+    /// environment.
+    pub fn sample_direction(
+        &self,
+        origin: Point,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Vec3, Option<&'a dyn Object>)> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let index = ((sampler.next_1d() * self.len() as f64) as usize).min(self.len() - 1);
+        match self.lights.get(index) {
+            Some(&light) => Some((light.sample_direction(origin, sampler)?, Some(light))),
+            None => Some((self.environment.sample_direction(sampler)?, None)),
+        }
+    }
+
+    /// The probability density, with respect to solid angle, of [`Lights::sample_direction`]
+    /// picking the given light and then the given direction from the given origin towards it.
+    ///
+    /// This only looks at the given light, so that it doesn't have to check every light in the
+    /// scene. If the light came from a hit, then it might not be the same object that got
+    /// collected, like the copies of the emissive triangles of a mesh, but it always has the
+    /// same shape. If there are no objects to sample, then this is always zero.
+    pub fn object_pdf(&self, light: &dyn Object, origin: Point, direction: Vec3) -> f64 {
+        if self.lights.is_empty() {
+            return 0.;
+        }
+        light.direction_pdf(origin, direction) / self.len() as f64
+    }
+
+    /// The probability density, with respect to solid angle, of [`Lights::sample_direction`]
+    /// picking the environment and then the given direction. This is zero if the environment
+    /// doesn't support sampling.
+    pub fn environment_pdf(&self, direction: Vec3) -> f64 {
+        if self.environment.supports_sampling() {
+            self.environment.direction_pdf(direction) / self.len() as f64
+        } else {
+            0.
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! This module provides the [`Mesh`] type.
+
+use super::{Aabb, Bvh, Hit, Object, Scene, Triangle};
+use crate::{
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal, SharedMaterial},
+    ray::Ray,
+    texture::ImageTexture,
+    vector::{v, Colour, Point, Vec3},
+};
+use color_eyre::{eyre::Context, Result};
+use std::{path::Path, sync::Arc};
+
+/// A mesh of triangles, usually loaded from a Wavefront OBJ file.
+pub struct Mesh {
+    /// All the triangles in the mesh.
+    triangles: Bvh,
+
+    /// Copies of the triangles which emit light, so that they can be sampled as lights.
+    emissive_triangles: Vec<Triangle<SharedMaterial>>,
+}
+
+/// Options for loading a [`Mesh`] from an OBJ file.
+#[derive(Clone)]
+pub struct MeshOpts {
+    /// A material to use for every triangle, overriding any materials from the MTL file.
+    pub material: Option<SharedMaterial>,
+
+    /// How much to scale the mesh by, before translating it.
+    pub scale: f64,
+
+    /// How far to translate the mesh, after scaling it.
+    pub offset: Vec3,
+}
+
+impl Default for MeshOpts {
+    fn default() -> Self {
+        Self {
+            material: None,
+            scale: 1.,
+            offset: Vec3::ZERO,
+        }
+    }
+}
+
+impl Mesh {
+    /// Create a new mesh from the given triangles.
+    pub fn new(triangles: Vec<Triangle<SharedMaterial>>) -> Self {
+        let emissive_triangles = triangles
+            .iter()
+            .filter(|triangle| triangle.material().is_emissive())
+            .cloned()
+            .collect();
+
+        Self {
+            emissive_triangles,
+            triangles: Bvh::new(
+                triangles
+                    .into_iter()
+                    .map(|triangle| Box::new(triangle) as Box<dyn Object + Sync + Send>)
+                    .collect::<Scene>(),
+            ),
+        }
+    }
+
+    /// Load a mesh from a Wavefront OBJ file.
+    ///
+    /// Materials from the MTL file referenced by the OBJ file are mapped onto our own materials.
+    /// Emissive materials become [`DiffuseLight`], transparent materials become [`Dielectric`],
+    /// shiny materials become [`Metal`], and everything else becomes [`Lambertian`], using the
+    /// diffuse texture map if there is one. Faces without a material get a grey Lambertian
+    /// material.
+    pub fn load_obj(path: impl AsRef<Path>, opts: MeshOpts) -> Result<Self> {
+        let path = path.as_ref();
+        let (models, materials) = tobj::load_obj(
+            path,
+            &tobj::LoadOptions {
+                single_index: true,
+                triangulate: true,
+                ignore_points: true,
+                ignore_lines: true,
+            },
+        )
+        .wrap_err_with(|| format!("When trying to load OBJ file {}", path.display()))?;
+
+        let default_material: SharedMaterial = Arc::new(Lambertian::new(v!(0.5)));
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let materials: Vec<SharedMaterial> = match opts.material {
+            Some(_) => vec![],
+            None => materials
+                .wrap_err_with(|| format!("When trying to load MTL file for {}", path.display()))?
+                .iter()
+                .map(|material| convert_material(material, directory))
+                .collect::<Result<_>>()?,
+        };
+
+        let scale = opts.scale;
+        let mut triangles = Vec::new();
+
+        for model in models {
+            let mesh = &model.mesh;
+
+            let material = match (&opts.material, mesh.material_id) {
+                (Some(material), _) => material.clone(),
+                (None, Some(id)) => materials.get(id).unwrap_or(&default_material).clone(),
+                (None, None) => default_material.clone(),
+            };
+
+            let position = |index: u32| -> Point {
+                let i = 3 * index as usize;
+                let p = &mesh.positions;
+                scale * v!(p[i], p[i + 1], p[i + 2]) + opts.offset
+            };
+            let normal = |index: u32| -> Vec3 {
+                let i = 3 * index as usize;
+                let n = &mesh.normals;
+                v!(n[i], n[i + 1], n[i + 2])
+            };
+            let texcoord = |index: u32| -> (f64, f64) {
+                let i = 2 * index as usize;
+                let t = &mesh.texcoords;
+                (t[i] as f64, t[i + 1] as f64)
+            };
+
+            for face in mesh.indices.chunks_exact(3) {
+                // A negative scale flips the handedness, so the winding order needs to flip too
+                let face = if scale < 0. {
+                    [face[0], face[2], face[1]]
+                } else {
+                    [face[0], face[1], face[2]]
+                };
+                let vertices = face.map(position);
+
+                let triangle = if mesh.normals.is_empty() {
+                    Triangle::new(vertices, material.clone())
+                } else {
+                    let normals = face.map(normal);
+                    let normals = if scale < 0. {
+                        normals.map(|n| -n)
+                    } else {
+                        normals
+                    };
+                    Triangle::with_vertex_normals(vertices, normals, material.clone())
+                };
+
+                triangles.push(if mesh.texcoords.is_empty() {
+                    triangle
+                } else {
+                    triangle.with_vertex_uvs(face.map(texcoord))
+                });
+            }
+        }
+
+        Ok(Self::new(triangles))
+    }
+
+    /// How many triangles are in this mesh?
+    pub fn len(&self) -> usize {
+        self.triangles.len()
+    }
+
+    /// Does this mesh contain no triangles?
+    pub fn is_empty(&self) -> bool {
+        self.triangles.is_empty()
+    }
+}
+
+/// Convert an MTL material into one of our materials. Texture paths are relative to the given
+/// directory.
+fn convert_material(material: &tobj::Material, directory: &Path) -> Result<SharedMaterial> {
+    let to_colour = |[r, g, b]: [f32; 3]| -> Colour { v!(r, g, b) };
+
+    let diffuse = material.diffuse.map(to_colour).unwrap_or(v!(0.5));
+    let specular = material.specular.map(to_colour).unwrap_or(v!(0));
+    let illumination_model = material.illumination_model.unwrap_or(2);
+
+    // The emissive colour isn't part of the original MTL spec, so tobj doesn't parse it for us
+    let emissive = material
+        .unknown_param
+        .get("Ke")
+        .and_then(|ke| {
+            let components: Vec<f64> = ke
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .ok()?;
+            match components[..] {
+                [r, g, b] => Some(v!(r, g, b)),
+                [x] => Some(v!(x)),
+                _ => None,
+            }
+        })
+        .unwrap_or(v!(0));
+
+    // Illumination models 4, 6, 7, and 9 all involve transparency or refraction
+    let transparent = material.dissolve.is_some_and(|dissolve| dissolve < 1.)
+        || matches!(illumination_model, 4 | 6 | 7 | 9);
+
+    // Illumination models 3 and 5 are reflective
+    let reflective = matches!(illumination_model, 3 | 5) && !specular.is_zero();
+
+    Ok(if !emissive.is_zero() {
+        Arc::new(DiffuseLight::new(emissive))
+    } else if transparent {
+        let refractive_index = material.optical_density.unwrap_or(1.5) as f64;
+        Arc::new(Dielectric::new(v!(1), refractive_index))
+    } else if reflective {
+        // The specular exponent is in [0, 1000], with higher being shinier
+        let shininess = material.shininess.unwrap_or(1000.).clamp(0., 1000.) as f64;
+        Arc::new(Metal::new(specular, 1. - shininess / 1000.))
+    } else if let Some(texture) = &material.diffuse_texture {
+        Arc::new(Lambertian::new(ImageTexture::load(
+            directory.join(texture),
+        )?))
+    } else {
+        Arc::new(Lambertian::new(diffuse))
+    })
+}
+
+impl Object for Mesh {
+    fn hit(&self, ray: &Ray, bounds: (f64, f64)) -> Option<Hit<'_>> {
+        self.triangles.hit(ray, bounds)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.triangles.bounding_box()
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Object>) {
+        for triangle in &self.emissive_triangles {
+            lights.push(triangle);
+        }
+    }
+}
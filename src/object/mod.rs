@@ -1,25 +1,62 @@
 //! This module handles various objects that can exist in a scene.
 
+mod aabb;
+mod bvh;
+mod identified;
+mod lights;
+mod mesh;
 mod sphere;
+mod triangle;
 
 use crate::{
-    material::Reflection,
+    material::Material,
     ray::Ray,
+    sampler::Sampler,
     vector::{Point, Vec3},
 };
 
-pub use self::sphere::Sphere;
+pub use self::{
+    aabb::Aabb,
+    bvh::Bvh,
+    identified::{identify_objects, Identified},
+    lights::Lights,
+    mesh::{Mesh, MeshOpts},
+    sphere::Sphere,
+    triangle::Triangle,
+};
 
 /// An object which a ray could hit.
-pub trait Object {
+///
+/// Objects must be [`Send`] and [`Sync`] so that the scene can be rendered on several threads.
+pub trait Object: Send + Sync {
     /// Does the give ray hit this object? If so, return information about the hit.
     ///
     /// It is assumed that `bounds.0 <= bounds.1`.
-    fn hit(&self, ray: &Ray, bounds: (f64, f64)) -> Option<Hit>;
+    fn hit(&self, ray: &Ray, bounds: (f64, f64)) -> Option<Hit<'_>>;
+
+    /// Get an axis-aligned box which completely contains this object.
+    fn bounding_box(&self) -> Aabb;
+
+    /// Add every part of this object which emits light to the given list, so that the renderer can
+    /// sample light from them directly. See [`Lights`].
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Object>);
+
+    /// Pick a random direction from the given origin towards a point on this object. Objects
+    /// which don't support being sampled as lights return `None`.
+    fn sample_direction(&self, _origin: Point, _sampler: &mut dyn Sampler) -> Option<Vec3> {
+        None
+    }
+
+    /// The probability density, with respect to solid angle, of [`Object::sample_direction`]
+    /// picking the given direction from the given origin. This is zero if the direction misses
+    /// the object.
+    fn direction_pdf(&self, _origin: Point, _direction: Vec3) -> f64 {
+        0.
+    }
 }
 
 /// Information about how a ray hit an object.
-pub struct Hit {
+pub struct Hit<'a> {
     /// The point at which the ray hit the object.
     pub intersection_point: Point,
 
@@ -33,8 +70,18 @@ pub struct Hit {
     /// The parameter `t` where the ray intersected the object. See [`Ray::at`].
     pub t: f64,
 
-    /// A possible reflection from the object.
-    pub reflection: Option<Reflection>,
+    /// The texture coordinates of the intersection point. Both coordinates should be in `[0, 1]`.
+    pub uv: (f64, f64),
+
+    /// The material of the object at the intersection point.
+    pub material: &'a dyn Material,
+
+    /// The ID of the object which was hit, if it was given one with [`Identified`].
+    pub object_id: Option<u32>,
+
+    /// The object which was hit, if it emits light. The renderer uses this to work out how
+    /// likely it was that sampling the lights would have picked the same direction.
+    pub light: Option<&'a dyn Object>,
 }
 
 /// A collection of objects. If your scene only contains objects of one type, just use a vec of
@@ -55,35 +102,44 @@ macro_rules! dyn_scene_vec {
 #[allow(unused_imports)]
 pub(crate) use dyn_scene_vec;
 
-impl Object for Scene {
-    fn hit(&self, ray: &Ray, bounds: (f64, f64)) -> Option<Hit> {
-        self.iter()
-            .map(|object| object.hit(ray, bounds))
-            .fold(None, |a, b| match (a, b) {
-                (Some(a), Some(b)) => {
-                    if a.t < b.t {
-                        Some(a)
-                    } else {
-                        Some(b)
-                    }
-                }
-                (Some(a), None) => Some(a),
-                (None, Some(b)) => Some(b),
-                (None, None) => None,
-            })
+// This lets scenes hold objects of different types, and lets objects be wrapped without knowing
+// their type.
+impl<O> Object for Box<O>
+where
+    O: Object + ?Sized,
+{
+    fn hit(&self, ray: &Ray, bounds: (f64, f64)) -> Option<Hit<'_>> {
+        (**self).hit(ray, bounds)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        (**self).bounding_box()
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Object>) {
+        (**self).collect_lights(lights);
+    }
+
+    fn sample_direction(&self, origin: Point, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        (**self).sample_direction(origin, sampler)
+    }
+
+    fn direction_pdf(&self, origin: Point, direction: Vec3) -> f64 {
+        (**self).direction_pdf(origin, direction)
     }
 }
 
-/// Generate a random scene.
-pub fn random_scene() -> Scene {
+/// Generate a random scene. The same seed always gives the same scene.
+pub fn random_scene(seed: u64) -> Scene {
     use crate::{
         material::{Dielectric, Lambertian, Metal},
+        random::seeded_rng,
         vector::v,
     };
+    use rand::Rng;
 
-    fn rand_f64() -> f64 {
-        rand::random()
-    }
+    let mut rng = seeded_rng(seed);
+    let mut rand_f64 = || -> f64 { rng.gen() };
 
     let mut objects: Scene = Vec::with_capacity(4 + (11usize + 11 + 1).pow(2));
 
@@ -101,7 +157,6 @@ pub fn random_scene() -> Scene {
             let material_choice = rand_f64();
             let centre = v!(a + 0.75 * rand_f64(), 0.2, b + 0.75 * rand_f64());
 
-            #[allow(illegal_floating_point_literal_pattern)]
             objects.push(match material_choice {
                 0.0..=0.8 => Box::new(Sphere::new(centre, 0.2, Lambertian::new(v!(rand_f64())))),
                 0.8..=0.95 => Box::new(Sphere::new(
@@ -141,9 +196,9 @@ pub fn random_scene() -> Scene {
 // optimisations.
 impl<T> Object for Vec<T>
 where
-    T: Object + Sync,
+    T: Object,
 {
-    fn hit(&self, ray: &Ray, bounds: (f64, f64)) -> Option<Hit> {
+    fn hit(&self, ray: &Ray, bounds: (f64, f64)) -> Option<Hit<'_>> {
         self.iter()
             .map(|object| object.hit(ray, bounds))
             .fold(None, |a, b| match (a, b) {
@@ -159,4 +214,15 @@ where
                 (None, None) => None,
             })
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.iter()
+            .fold(Aabb::EMPTY, |acc, object| acc.union(object.bounding_box()))
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Object>) {
+        for object in self {
+            object.collect_lights(lights);
+        }
+    }
 }
@@ -1,7 +1,13 @@
 //! This module provides the [`Sphere`] type.
 
-use super::{Hit, Object};
-use crate::{material::Material, ray::Ray, vector::Point};
+use super::{Aabb, Hit, Object};
+use crate::{
+    material::Material,
+    ray::Ray,
+    sampler::Sampler,
+    vector::{Point, Vec3},
+};
+use core::f64::consts::PI;
 
 /// A simple sphere.
 #[derive(Clone, Debug, PartialEq)]
@@ -27,7 +33,7 @@ impl<M: Material> Sphere<M> {
 }
 
 impl<M: Material> Object for Sphere<M> {
-    fn hit(&self, ray: &Ray, bounds: (f64, f64)) -> Option<Hit> {
+    fn hit(&self, ray: &Ray, bounds: (f64, f64)) -> Option<Hit<'_>> {
         let centre_to_ray_origin = ray.origin - self.centre;
 
         let a = ray.direction.dot(ray.direction);
@@ -53,25 +59,90 @@ impl<M: Material> Object for Sphere<M> {
 
             let intersection_point = ray.at(t);
             let surface_normal = (intersection_point - self.centre).normalise();
+            let uv = sphere_uv(surface_normal);
             let (surface_normal, front_face) = if ray.direction.dot(surface_normal) > 0. {
                 (-surface_normal, false)
             } else {
                 (surface_normal, true)
             };
 
-            let mut hit = Hit {
+            Some(Hit {
                 intersection_point,
                 surface_normal,
                 front_face,
                 t,
-                reflection: None,
-            };
+                uv,
+                material: &self.material,
+                object_id: None,
+                light: self.material.is_emissive().then_some(self as &dyn Object),
+            })
+        } else {
+            None
+        }
+    }
 
-            hit.reflection = self.material.scatter(ray, &hit);
+    fn bounding_box(&self) -> Aabb {
+        let radius = Vec3::splat(self.radius.abs());
+        Aabb::new(self.centre - radius, self.centre + radius)
+    }
 
-            Some(hit)
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Object>) {
+        if self.material.is_emissive() {
+            lights.push(self);
+        }
+    }
+
+    fn sample_direction(&self, origin: Point, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        let to_centre = self.centre - origin;
+        let distance_squared = to_centre.dot(to_centre);
+        let radius_squared = self.radius * self.radius;
+
+        // From inside the sphere, just pick a uniformly random point on the surface
+        if distance_squared <= radius_squared {
+            let point =
+                self.centre + self.radius.abs() * Vec3::unit_vector_from_sample(sampler.next_2d());
+            return Some((point - origin).normalise());
+        }
+
+        // From outside, pick a uniformly random direction in the cone which the sphere fills
+        let cos_theta_max = (1. - radius_squared / distance_squared).sqrt();
+        let (r1, r2) = sampler.next_2d();
+        let cos_theta = 1. + r1 * (cos_theta_max - 1.);
+        let sin_theta = (1. - cos_theta * cos_theta).sqrt();
+        let phi = 2. * PI * r2;
+
+        let axis = to_centre.normalise();
+        let (tangent, bitangent) = axis.orthonormal_basis();
+        Some(sin_theta * phi.cos() * tangent + sin_theta * phi.sin() * bitangent + cos_theta * axis)
+    }
+
+    fn direction_pdf(&self, origin: Point, direction: Vec3) -> f64 {
+        let Some(hit) = self.hit(&Ray::new(origin, direction), (1e-5, f64::INFINITY)) else {
+            return 0.;
+        };
+
+        let to_centre = self.centre - origin;
+        let distance_squared = to_centre.dot(to_centre);
+        let radius_squared = self.radius * self.radius;
+
+        if distance_squared <= radius_squared {
+            let cos = hit.surface_normal.dot(direction.normalise()).abs();
+            let area = 4. * PI * radius_squared;
+            hit.t * hit.t / (cos * area)
         } else {
-            None
+            let cos_theta_max = (1. - radius_squared / distance_squared).sqrt();
+            1. / (2. * PI * (1. - cos_theta_max))
         }
     }
 }
+
+/// Get the UV coordinates of a point on the unit sphere.
+///
+/// U goes around the sphere from -X, through +Z, +X, and -Z, back to -X, and V goes from the south
+/// pole at -Y to the north pole at +Y.
+fn sphere_uv(point: Point) -> (f64, f64) {
+    let theta = (-point.y).clamp(-1., 1.).acos();
+    let phi = f64::atan2(-point.z, point.x) + PI;
+
+    (phi / (2. * PI), theta / PI)
+}
@@ -0,0 +1,178 @@
+//! This module provides the [`Triangle`] type.
+
+use super::{Aabb, Hit, Object};
+use crate::{
+    material::Material,
+    ray::Ray,
+    sampler::Sampler,
+    vector::{Point, Vec3},
+};
+
+/// A single triangle, optionally with a normal vector at each vertex for smooth shading.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Triangle<M: Material> {
+    /// The three vertices of the triangle.
+    vertices: [Point; 3],
+
+    /// The normal vectors at each vertex, which get interpolated across the face of the triangle.
+    /// These should be pre-normalised. If these are not given, then we just use the geometric
+    /// normal of the triangle.
+    vertex_normals: Option<[Vec3; 3]>,
+
+    /// The texture coordinates at each vertex, which get interpolated across the face of the
+    /// triangle. If these are not given, then the UV coordinates are the barycentric coordinates
+    /// of the second and third vertices.
+    vertex_uvs: Option<[(f64, f64); 3]>,
+
+    material: M,
+}
+
+impl<M: Material> Triangle<M> {
+    /// Create a new flat-shaded triangle.
+    pub fn new(vertices: [Point; 3], material: M) -> Self {
+        Self {
+            vertices,
+            vertex_normals: None,
+            vertex_uvs: None,
+            material,
+        }
+    }
+
+    /// Create a new triangle with the given normal vectors at each vertex, for smooth shading.
+    pub fn with_vertex_normals(
+        vertices: [Point; 3],
+        vertex_normals: [Vec3; 3],
+        material: M,
+    ) -> Self {
+        Self {
+            vertices,
+            vertex_normals: Some(vertex_normals.map(Vec3::normalise)),
+            vertex_uvs: None,
+            material,
+        }
+    }
+
+    /// Give this triangle texture coordinates at each vertex.
+    pub fn with_vertex_uvs(mut self, vertex_uvs: [(f64, f64); 3]) -> Self {
+        self.vertex_uvs = Some(vertex_uvs);
+        self
+    }
+
+    /// The material of this triangle.
+    pub fn material(&self) -> &M {
+        &self.material
+    }
+}
+
+impl<M: Material> Object for Triangle<M> {
+    fn hit(&self, ray: &Ray, bounds: (f64, f64)) -> Option<Hit<'_>> {
+        // This is the Möller-Trumbore intersection algorithm
+        let [a, b, c] = self.vertices;
+        let edge1 = b - a;
+        let edge2 = c - a;
+
+        let p = ray.direction.cross(edge2);
+        let determinant = edge1.dot(p);
+
+        // The ray is parallel to the plane of the triangle
+        if determinant.abs() < 1e-12 {
+            return None;
+        }
+
+        let inverse_determinant = determinant.recip();
+        let a_to_origin = ray.origin - a;
+
+        let u = a_to_origin.dot(p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = a_to_origin.cross(edge1);
+        let v = ray.direction.dot(q) * inverse_determinant;
+        if v < 0. || u + v > 1. {
+            return None;
+        }
+
+        let t = edge2.dot(q) * inverse_determinant;
+        let (lower, upper) = bounds;
+        if !(lower..=upper).contains(&t) {
+            return None;
+        }
+
+        let geometric_normal = edge1.cross(edge2).normalise();
+        let front_face = ray.direction.dot(geometric_normal) < 0.;
+
+        let surface_normal = match self.vertex_normals {
+            Some([na, nb, nc]) => ((1. - u - v) * na + u * nb + v * nc).normalise(),
+            None => geometric_normal,
+        };
+        let surface_normal = if front_face {
+            surface_normal
+        } else {
+            -surface_normal
+        };
+
+        let uv = match self.vertex_uvs {
+            Some([(ua, va), (ub, vb), (uc, vc)]) => (
+                (1. - u - v) * ua + u * ub + v * uc,
+                (1. - u - v) * va + u * vb + v * vc,
+            ),
+            None => (u, v),
+        };
+
+        Some(Hit {
+            intersection_point: ray.at(t),
+            surface_normal,
+            front_face,
+            t,
+            uv,
+            material: &self.material,
+            object_id: None,
+            light: self.material.is_emissive().then_some(self as &dyn Object),
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let [a, b, c] = self.vertices;
+
+        // Pad the box slightly so that axis-aligned triangles don't have a flat bounding box
+        let padding = Vec3::splat(1e-6);
+        let bounding_box = Aabb::new(a, b).union_point(c);
+        Aabb::new(bounding_box.min - padding, bounding_box.max + padding)
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Object>) {
+        if self.material.is_emissive() {
+            lights.push(self);
+        }
+    }
+
+    fn sample_direction(&self, origin: Point, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        // Pick a uniformly random point on the triangle
+        let [a, b, c] = self.vertices;
+        let (r1, r2) = sampler.next_2d();
+        let sqrt_r1 = r1.sqrt();
+        let point = (1. - sqrt_r1) * a + sqrt_r1 * (1. - r2) * b + sqrt_r1 * r2 * c;
+
+        let direction = point - origin;
+        (!direction.is_zero()).then(|| direction.normalise())
+    }
+
+    fn direction_pdf(&self, origin: Point, direction: Vec3) -> f64 {
+        let ray = Ray::new(origin, direction);
+        let Some(hit) = self.hit(&ray, (1e-5, f64::INFINITY)) else {
+            return 0.;
+        };
+
+        let [a, b, c] = self.vertices;
+        let scaled_normal = (b - a).cross(c - a);
+        let area = 0.5 * scaled_normal.len();
+        let cos = ray.direction.dot(scaled_normal.normalise()).abs();
+
+        if cos < 1e-12 {
+            0.
+        } else {
+            hit.t * hit.t / (cos * area)
+        }
+    }
+}
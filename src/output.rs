@@ -0,0 +1,110 @@
+//! This module handles saving rendered images to files.
+
+use crate::{aov::Aov, tonemap::ViewTransform};
+use color_eyre::{eyre::Context, Result};
+use image::{codecs::hdr::HdrEncoder, ImageFormat, Rgb, Rgb32FImage};
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+/// The format to save an image in, based on the file extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// An OpenEXR file, which keeps the full linear float image.
+    OpenExr,
+
+    /// A Radiance HDR file, which keeps the full linear float image.
+    RadianceHdr,
+
+    /// Any other image format, which gets tone mapped down to 8 bits per channel.
+    LowDynamicRange,
+}
+
+impl OutputFormat {
+    /// Work out the output format from the extension of the path.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        match ImageFormat::from_path(path) {
+            Ok(ImageFormat::OpenExr) => Self::OpenExr,
+            Ok(ImageFormat::Hdr) => Self::RadianceHdr,
+            _ => Self::LowDynamicRange,
+        }
+    }
+
+    /// Does this format keep the full dynamic range of the image?
+    pub fn is_high_dynamic_range(self) -> bool {
+        matches!(self, Self::OpenExr | Self::RadianceHdr)
+    }
+}
+
+/// Save the linear float image to the given path. The format is chosen by the file extension.
+///
+/// `.exr` and `.hdr` files keep the full linear radiance of the image and ignore the view
+/// transform. Every other format gets the view transform applied before saving.
+pub fn save_image(
+    float_img: &Rgb32FImage,
+    path: impl AsRef<Path>,
+    view_transform: &ViewTransform,
+) -> Result<()> {
+    let path = path.as_ref();
+
+    match OutputFormat::from_path(path) {
+        OutputFormat::OpenExr => float_img
+            .save_with_format(path, ImageFormat::OpenExr)
+            .wrap_err_with(|| format!("When trying to save EXR image to {}", path.display())),
+        OutputFormat::RadianceHdr => {
+            let file = File::create(path)
+                .wrap_err_with(|| format!("When trying to create {}", path.display()))?;
+            let pixels: Vec<Rgb<f32>> = float_img.pixels().copied().collect();
+
+            HdrEncoder::new(BufWriter::new(file))
+                .encode(
+                    &pixels,
+                    float_img.width() as usize,
+                    float_img.height() as usize,
+                )
+                .wrap_err_with(|| format!("When trying to save HDR image to {}", path.display()))
+        }
+        OutputFormat::LowDynamicRange => view_transform
+            .apply_to_image(float_img)
+            .save(path)
+            .wrap_err_with(|| format!("When trying to save image to {}", path.display())),
+    }
+}
+
+/// Save an extra pass to the given path. The format is chosen by the file extension.
+///
+/// `.exr` and `.hdr` files keep the raw values of the pass. Every other format gets the values
+/// scaled so that they can be seen, with [`Aov::to_display_image`].
+pub fn save_aov(
+    aov: Aov,
+    float_img: &Rgb32FImage,
+    path: impl AsRef<Path>,
+    view_transform: &ViewTransform,
+) -> Result<()> {
+    let path = path.as_ref();
+
+    if OutputFormat::from_path(path).is_high_dynamic_range() {
+        save_image(float_img, path, view_transform)
+    } else {
+        aov.to_display_image(float_img, view_transform)
+            .save(path)
+            .wrap_err_with(|| format!("When trying to save image to {}", path.display()))
+    }
+}
+
+/// The path to save an extra pass to, next to the image at the given path. For example, the
+/// albedo pass for `out.exr` goes in `out.albedo.exr`.
+pub fn aov_path(path: impl AsRef<Path>, aov: Aov) -> PathBuf {
+    let path = path.as_ref();
+    let mut file_name = path.file_stem().unwrap_or_default().to_owned();
+    file_name.push(".");
+    file_name.push(aov.name());
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+
+    path.with_file_name(file_name)
+}
@@ -0,0 +1,280 @@
+//! This module draws images into the pixel buffer of a preview window.
+
+use crate::{tonemap::ViewTransform, vector::Colour};
+use image::Rgb32FImage;
+use rayon::{
+    iter::{IndexedParallelIterator, ParallelIterator},
+    slice::ParallelSliceMut,
+};
+
+/// Where an image gets drawn in a window. The image is scaled to fit the window while keeping its
+/// aspect ratio, and centred, with black bars filling the rest of the window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    /// The X coordinate of the left column of the image in the window.
+    pub x: u32,
+
+    /// The Y coordinate of the top row of the image in the window.
+    pub y: u32,
+
+    /// The width of the image in the window.
+    pub width: u32,
+
+    /// The height of the image in the window.
+    pub height: u32,
+
+    /// How many pixels of the image go across each pixel of the window.
+    pub scale: f64,
+
+    /// The width of the image itself.
+    pub image_width: u32,
+
+    /// The height of the image itself.
+    pub image_height: u32,
+}
+
+impl Viewport {
+    /// Fit an image of the given size into a window of the given size.
+    pub fn fit(image_width: u32, image_height: u32, window_width: u32, window_height: u32) -> Self {
+        let scale = f64::max(
+            image_width as f64 / window_width.max(1) as f64,
+            image_height as f64 / window_height.max(1) as f64,
+        );
+        let width = ((image_width as f64 / scale).round() as u32).clamp(1, window_width.max(1));
+        let height = ((image_height as f64 / scale).round() as u32).clamp(1, window_height.max(1));
+
+        Self {
+            x: (window_width.saturating_sub(width)) / 2,
+            y: (window_height.saturating_sub(height)) / 2,
+            width,
+            height,
+            scale,
+            image_width,
+            image_height,
+        }
+    }
+
+    /// Get the pixel of the image which is shown at the given point in the window, if the point
+    /// is on the image rather than on the black bars around it.
+    pub fn image_pixel(&self, window_x: f64, window_y: f64) -> Option<(u32, u32)> {
+        let (x, y) = (window_x - self.x as f64, window_y - self.y as f64);
+        if x < 0. || y < 0. || x >= self.width as f64 || y >= self.height as f64 {
+            return None;
+        }
+
+        Some((
+            ((x * self.scale) as u32).min(self.image_width - 1),
+            ((y * self.scale) as u32).min(self.image_height - 1),
+        ))
+    }
+}
+
+/// Draw the given image into the pixel buffer of a window with the given size, where each pixel
+/// is `0x00RRGGBB`. Returns where the image ended up in the window.
+///
+/// Each pixel of the window gets the average of all the pixels of the image that it covers,
+/// weighted by how much of each one it covers, so shrinking a noisy image doesn't make it look
+/// any noisier than it is. The averaging is done on the linear colours, before the view
+/// transform.
+///
+/// # Panics
+///
+/// Panics if the buffer is smaller than the window.
+pub fn draw_image(
+    image: &Rgb32FImage,
+    view_transform: &ViewTransform,
+    buffer: &mut [u32],
+    window_width: u32,
+    window_height: u32,
+) -> Viewport {
+    let viewport = Viewport::fit(image.width(), image.height(), window_width, window_height);
+    let columns = coverage(viewport.width, image.width(), viewport.scale);
+    let rows = coverage(viewport.height, image.height(), viewport.scale);
+
+    buffer[..window_width as usize * window_height as usize]
+        .par_chunks_mut(window_width as usize)
+        .enumerate()
+        .for_each(|(y, row)| {
+            row.fill(0);
+
+            let Some(spans) = (y as u32)
+                .checked_sub(viewport.y)
+                .and_then(|y| rows.get(y as usize))
+            else {
+                return;
+            };
+
+            let start = viewport.x as usize;
+            for (pixel, column_spans) in row[start..start + viewport.width as usize]
+                .iter_mut()
+                .zip(&columns)
+            {
+                let mut colour = Colour::ZERO;
+                for &(j, y_weight) in spans {
+                    for &(i, x_weight) in column_spans {
+                        colour += Colour::from(*image.get_pixel(i, j)) * (x_weight * y_weight);
+                    }
+                }
+
+                let [r, g, b] = view_transform.apply_u8(colour).map(u32::from);
+                *pixel = (r << 16) | (g << 8) | b;
+            }
+        });
+
+    viewport
+}
+
+/// For each of the given number of pixels in a row or column of the window, find which pixels of
+/// the image it covers and how much of its area each one makes up. The weights for each window
+/// pixel add up to one.
+fn coverage(window_length: u32, image_length: u32, scale: f64) -> Vec<Vec<(u32, f64)>> {
+    (0..window_length)
+        .map(|pixel| {
+            let start = pixel as f64 * scale;
+            let end = ((pixel + 1) as f64 * scale).min(image_length as f64);
+            let first = (start.floor() as u32).min(image_length - 1);
+            let last = (end.ceil() as u32).clamp(first + 1, image_length);
+
+            let spans: Vec<(u32, f64)> = (first..last)
+                .map(|i| {
+                    let overlap = end.min((i + 1) as f64) - start.max(i as f64);
+                    (i, overlap.max(0.))
+                })
+                .collect();
+
+            // Near the edges, rounding can leave a window pixel without any overlap at all, so
+            // we fall back to the nearest pixel of the image
+            let total: f64 = spans.iter().map(|&(_, weight)| weight).sum();
+            if total > 0. {
+                spans
+                    .into_iter()
+                    .map(|(i, weight)| (i, weight / total))
+                    .collect()
+            } else {
+                vec![(first, 1.)]
+            }
+        })
+        .collect()
+}
+
+/// The width of each character of the HUD font in font pixels, not counting the gap after it.
+const GLYPH_WIDTH: u32 = 5;
+
+/// The height of each character of the HUD font in font pixels.
+const GLYPH_HEIGHT: u32 = 7;
+
+/// Draw lines of text into the pixel buffer of a window with the given size, with their top left
+/// corner at the given point. The text is white on a darkened box so that it can be read over
+/// any image, and each pixel of the font is drawn as a square of the given size.
+///
+/// The font only has capital letters, digits, and a little punctuation, so lowercase letters are
+/// drawn as capitals, and anything else as a question mark.
+///
+/// # Panics
+///
+/// Panics if the buffer is smaller than the window.
+pub fn draw_text(
+    buffer: &mut [u32],
+    window_width: u32,
+    window_height: u32,
+    (x, y): (u32, u32),
+    lines: &[String],
+    scale: u32,
+) {
+    let scale = scale.max(1);
+    let (advance, line_height) = ((GLYPH_WIDTH + 1) * scale, (GLYPH_HEIGHT + 3) * scale);
+    let longest_line = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0) as u32;
+
+    // Darken a box behind the text, with a margin of a couple of font pixels
+    let margin = 2 * scale;
+    let right = (x + longest_line * advance + 2 * margin).min(window_width);
+    let bottom = (y + lines.len() as u32 * line_height + 2 * margin).min(window_height);
+    for j in y.min(bottom)..bottom {
+        for pixel in &mut buffer
+            [(j * window_width + x.min(right)) as usize..(j * window_width + right) as usize]
+        {
+            *pixel = (*pixel >> 2) & 0x3f3f3f;
+        }
+    }
+
+    for (row, line) in lines.iter().enumerate() {
+        let top = y + margin + row as u32 * line_height + scale;
+        for (column, character) in line.chars().enumerate() {
+            let left = x + margin + column as u32 * advance;
+            for (glyph_y, bits) in glyph(character).into_iter().enumerate() {
+                for glyph_x in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - glyph_x)) == 0 {
+                        continue;
+                    }
+
+                    for j in 0..scale {
+                        for i in 0..scale {
+                            let (px, py) =
+                                (left + glyph_x * scale + i, top + glyph_y as u32 * scale + j);
+                            if px < window_width && py < window_height {
+                                buffer[(py * window_width + px) as usize] = 0xffffff;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The rows of the HUD font's glyph for the given character, from the top. The lowest five bits
+/// of each row are its pixels, with the leftmost pixel in the highest bit.
+fn glyph(character: char) -> [u8; GLYPH_HEIGHT as usize] {
+    match character.to_ascii_uppercase() {
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '0' => [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+        '1' => [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        '2' => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+        '3' => [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+        '4' => [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+        '5' => [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+        '6' => [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+        '7' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+        '9' => [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+        'A' => [0x0e, 0x11, 0x11, 0x11, 0x1f, 0x11, 0x11],
+        'B' => [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e],
+        'C' => [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e],
+        'D' => [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c],
+        'E' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f],
+        'F' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10],
+        'G' => [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f],
+        'H' => [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+        'I' => [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f],
+        'M' => [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'P' => [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10],
+        'Q' => [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d],
+        'R' => [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11],
+        'S' => [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e],
+        'T' => [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a],
+        'X' => [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04],
+        'Z' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f],
+        ':' => [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        '-' => [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        _ => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
@@ -0,0 +1,43 @@
+//! This module handles the random number generators used for rendering, so that renders can be
+//! reproduced exactly from a seed.
+
+use rand::SeedableRng;
+use rand_pcg::Pcg64Mcg;
+
+/// The random number generator used for rendering. It's fast, and it gives the same numbers on
+/// every platform.
+pub type RenderRng = Pcg64Mcg;
+
+/// Create a random number generator from a seed.
+pub fn seeded_rng(seed: u64) -> RenderRng {
+    RenderRng::seed_from_u64(seed)
+}
+
+/// Create a random number generator for one sample of one pixel.
+///
+/// Every sample of every pixel gets its own independent stream of random numbers, so the image
+/// doesn't depend on which thread renders which pixel.
+pub fn sample_rng(seed: u64, x: u32, y: u32, sample: u32) -> RenderRng {
+    let key = mix(mix(mix(seed) ^ x as u64) ^ ((y as u64) << 32 | sample as u64));
+    RenderRng::seed_from_u64(key)
+}
+
+/// Hash some numbers together into a single well-scrambled number.
+pub(crate) fn hash(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(0x2545_f491_4f6c_dd1d, |acc, &value| mix(acc ^ value))
+}
+
+/// Turn a hash into a uniformly distributed number in `[0, 1)`.
+pub(crate) fn hash_to_unit_float(hash: u64) -> f64 {
+    (hash >> 11) as f64 * (1. / (1u64 << 53) as f64)
+}
+
+/// Scramble the bits of a number, using the finaliser from SplitMix64.
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -2,7 +2,8 @@
 
 use crate::{
     material::Reflection,
-    object::Object,
+    object::{Hit, Lights, Object},
+    sampler::Sampler,
     vector::{v, Colour, Point, Vec3},
 };
 
@@ -16,6 +17,67 @@ pub struct Ray {
     pub direction: Vec3,
 }
 
+/// How many dimensions of each sample the camera uses. The first two are for the position within
+/// the pixel, and the next two are for the position on the lens.
+pub const CAMERA_DIMENSIONS: u32 = 4;
+
+/// How many dimensions of each sample get used at each bounce of a path. The first two are for
+/// scattering, the next five are for sampling the lights, and the last one is for Russian
+/// roulette.
+const DIMENSIONS_PER_BOUNCE: u32 = 8;
+
+/// How a path ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathEnd {
+    /// The path didn't hit anything and escaped to the sky.
+    Escaped,
+
+    /// The path hit a material which absorbed it.
+    Absorbed,
+
+    /// The path was terminated by Russian roulette.
+    RussianRoulette,
+
+    /// The path reached the maximum number of bounces.
+    MaxBounces,
+}
+
+/// Statistics about a single traced path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PathStats {
+    /// How many times the path bounced off a surface.
+    pub bounces: u16,
+
+    /// How many rays were cast into the scene for the path, including the shadow rays used to
+    /// sample the lights.
+    pub rays: u32,
+
+    /// How the path ended.
+    pub end: PathEnd,
+}
+
+/// Everything that tracing a path finds out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TracedPath {
+    /// The light arriving along the path.
+    pub colour: Colour,
+
+    /// The part of the colour which came straight from a light, or from a light after a single
+    /// bounce. The rest of the colour is indirect light.
+    pub direct: Colour,
+
+    /// The albedo of the first surface that the path hit, or black if it didn't hit anything.
+    /// See [`Material::albedo`](crate::material::Material::albedo).
+    pub albedo: Colour,
+
+    /// The surface normal where the path first hit something, facing back along the path, or
+    /// zero if it didn't hit anything.
+    pub normal: Vec3,
+
+    /// Statistics about the path.
+    pub stats: PathStats,
+}
+
 impl Ray {
     /// Create a new ray with the given origin and direction.
     pub fn new(origin: Point, direction: Vec3) -> Self {
@@ -31,29 +93,299 @@ impl Ray {
         self.origin + t * self.direction
     }
 
-    /// Trace this ray and determine its colour.
-    pub fn colour(&self, object: &impl Object, bounces: u16) -> Colour {
-        if bounces == 0 {
-            return v!(0);
+    /// Trace a path starting with this ray and determine its colour.
+    ///
+    /// At every diffuse surface, we sample light both by following the scattered ray and by
+    /// picking a direction towards one of the lights, and combine the two with multiple
+    /// importance sampling.
+    ///
+    /// After `min_bounces` bounces, paths get terminated randomly with Russian roulette, based on
+    /// how much light they could still carry. Surviving paths get brightened to make up for the
+    /// ones that were terminated, so this doesn't bias the result. Paths never go beyond
+    /// `max_bounces` bounces.
+    pub fn trace(
+        &self,
+        object: &impl Object,
+        lights: &Lights,
+        max_bounces: u16,
+        min_bounces: u16,
+        sampler: &mut dyn Sampler,
+    ) -> TracedPath {
+        let mut path = TracedPath {
+            colour: Colour::ZERO,
+            direct: Colour::ZERO,
+            albedo: Colour::ZERO,
+            normal: Vec3::ZERO,
+            stats: PathStats {
+                bounces: 0,
+                rays: 0,
+                end: PathEnd::MaxBounces,
+            },
+        };
+        if max_bounces == 0 {
+            return path;
         }
 
-        if let Some(hit) = object.hit(self, (1e-5, f64::INFINITY)) {
-            if let Some(Reflection {
+        let mut colour = Colour::ZERO;
+        let mut throughput = v!(1);
+        let mut ray = self.clone();
+
+        // Everything found before the second bounce is direct light, but the path could end
+        // before then
+        let mut direct = None;
+        let stats = &mut path.stats;
+
+        // The probability density of the material scattering the current ray, if it was
+        // scattered from a non-specular surface
+        let mut scatter_pdf = None;
+
+        loop {
+            let first_dimension = CAMERA_DIMENSIONS + stats.bounces as u32 * DIMENSIONS_PER_BOUNCE;
+
+            // If we could have also found this light by sampling the lights, then we need to
+            // weight its contribution so that we don't count it twice. The density of sampling
+            // the lights is only worked out when we actually find a light, since it means
+            // intersecting the light again
+            let emission_weight = |light_pdf: &dyn Fn() -> f64| match scatter_pdf {
+                Some(scatter_pdf) => power_heuristic(scatter_pdf, light_pdf()),
+                None => 1.,
+            };
+
+            stats.rays += 1;
+            let Some(hit) = object.hit(&ray, (1e-5, f64::INFINITY)) else {
+                let weight = emission_weight(&|| lights.environment_pdf(ray.direction));
+                let environment = lights.environment().colour(ray.direction);
+                colour += weight * throughput.mul_elementwise(environment);
+                stats.end = PathEnd::Escaped;
+                break;
+            };
+
+            if let Some(light) = hit.light {
+                let weight =
+                    emission_weight(&|| lights.object_pdf(light, ray.origin, ray.direction));
+                colour += weight * throughput.mul_elementwise(hit.material.emitted(&hit));
+            }
+            match stats.bounces {
+                0 => {
+                    path.albedo = hit.material.albedo(&hit);
+                    path.normal = hit.surface_normal;
+                }
+                1 => direct = Some(colour),
+                _ => {}
+            }
+
+            sampler.set_dimension(first_dimension);
+            let Some(Reflection {
                 reflected_ray,
                 colour_attenuation,
-            }) = hit.reflection
-            {
-                colour_attenuation.mul_elementwise(reflected_ray.colour(object, bounces - 1))
-            } else {
-                v!(0)
+                pdf,
+            }) = hit.material.scatter(&ray, &hit, sampler)
+            else {
+                stats.end = PathEnd::Absorbed;
+                break;
+            };
+
+            if pdf.is_some() {
+                sampler.set_dimension(first_dimension + 2);
+                colour += throughput
+                    .mul_elementwise(ray.sample_lights(object, lights, &hit, sampler, stats));
             }
-        } else {
-            let height = 0.5 * (self.direction.normalise().y + 1.);
-            debug_assert!(
-                (0.0..=1.0).contains(&height),
-                "The height must be in [0, 1]: {height}"
+
+            throughput = throughput.mul_elementwise(colour_attenuation);
+            stats.bounces += 1;
+
+            if stats.bounces >= max_bounces {
+                stats.end = PathEnd::MaxBounces;
+                break;
+            }
+
+            if stats.bounces >= min_bounces {
+                let survival_probability = throughput.max_component().min(1.);
+                sampler.set_dimension(first_dimension + 7);
+                if sampler.next_1d() >= survival_probability {
+                    stats.end = PathEnd::RussianRoulette;
+                    break;
+                }
+                throughput /= survival_probability;
+            }
+
+            ray = reflected_ray;
+            scatter_pdf = pdf;
+        }
+
+        path.colour = colour;
+        path.direct = direct.unwrap_or(colour);
+        path
+    }
+
+    /// Pick a direction towards one of the lights and work out how much light arrives at the hit
+    /// from that direction and gets reflected back along this ray. Any shadow ray gets counted in
+    /// the given statistics.
+    ///
+    /// Only the light which was picked counts, so if anything else is in the way, including
+    /// another light, then no light arrives.
+    fn sample_lights(
+        &self,
+        object: &impl Object,
+        lights: &Lights,
+        hit: &Hit,
+        sampler: &mut dyn Sampler,
+        stats: &mut PathStats,
+    ) -> Colour {
+        let origin = hit.intersection_point;
+        let Some((direction, light)) = lights.sample_direction(origin, sampler) else {
+            return Colour::ZERO;
+        };
+
+        let light_pdf = match light {
+            Some(light) => lights.object_pdf(light, origin, direction),
+            None => lights.environment_pdf(direction),
+        };
+        if light_pdf <= 0. {
+            return Colour::ZERO;
+        }
+
+        let brdf = hit.material.eval_brdf(self, hit, direction);
+        if brdf.is_zero() {
+            return Colour::ZERO;
+        }
+
+        let shadow_ray = Ray::new(origin, direction);
+        let (distance, emitted) = match light {
+            Some(light) => {
+                let Some(light_hit) = light.hit(&shadow_ray, (1e-5, f64::INFINITY)) else {
+                    return Colour::ZERO;
+                };
+                (light_hit.t - 1e-5, light_hit.material.emitted(&light_hit))
+            }
+            None => (f64::INFINITY, lights.environment().colour(direction)),
+        };
+
+        // Anything in the way casts a shadow
+        stats.rays += 1;
+        if object.hit(&shadow_ray, (1e-5, distance)).is_some() {
+            return Colour::ZERO;
+        }
+
+        let scatter_pdf = hit.material.scatter_pdf(self, hit, direction);
+        let weight = power_heuristic(light_pdf, scatter_pdf);
+        brdf.mul_elementwise(emitted) * (weight / light_pdf)
+    }
+}
+
+/// Weight a sample taken with the first probability density against another sampling strategy
+/// with the second probability density, using Veach's power heuristic with an exponent of 2.
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let pdf_squared = pdf * pdf;
+    let total = pdf_squared + other_pdf * other_pdf;
+    if total == 0. {
+        0.
+    } else {
+        pdf_squared / total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::{DiffuseLight, Lambertian},
+        object::{Scene, Sphere},
+        sampler::SamplerKind,
+    };
+
+    const ALBEDO: f64 = 0.5;
+    const RADIANCE: f64 = 4.;
+    const LIGHT_RADIUS: f64 = 0.5;
+    const LIGHT_HEIGHT: f64 = 2.;
+
+    /// A huge diffuse ball, which is practically a plane near the origin, under a small
+    /// spherical light straight above the origin. Everything else is black.
+    fn plane_under_light() -> Scene {
+        vec![
+            Box::new(Sphere::new(
+                v!(0, -1000, 0),
+                1000.,
+                Lambertian::new(v!(ALBEDO)),
+            )),
+            Box::new(Sphere::new(
+                v!(0, LIGHT_HEIGHT, 0),
+                LIGHT_RADIUS,
+                DiffuseLight::new(v!(RADIANCE)),
+            )),
+        ]
+    }
+
+    /// A ray which hits the plane at the origin.
+    fn camera_ray() -> Ray {
+        Ray::new(v!(1), v!(-1))
+    }
+
+    #[test]
+    fn both_weights_for_the_same_direction_add_up_to_one() {
+        let scene = plane_under_light();
+        let lights = Lights::new(&scene, &Colour::ZERO);
+        let ray = camera_ray();
+        let hit = scene.hit(&ray, (1e-5, f64::INFINITY)).unwrap();
+        let origin = hit.intersection_point;
+
+        for i in 0..256 {
+            let mut sampler = SamplerKind::Independent.create(0, (0, 0), i, 256);
+            let (direction, light) = lights.sample_direction(origin, &mut sampler).unwrap();
+            let light = light.expect("The only light is the sphere");
+
+            // The weight that sampling the lights gives to this direction
+            let light_pdf = lights.object_pdf(light, origin, direction);
+            let scatter_pdf = hit.material.scatter_pdf(&ray, &hit, direction);
+            assert!(light_pdf > 0. && scatter_pdf > 0.);
+            let light_weight = power_heuristic(light_pdf, scatter_pdf);
+
+            // The weight that the path gives to the light if the material scatters the ray in
+            // this direction and it hits the light
+            let scattered_ray = Ray::new(origin, direction);
+            let light_hit = scene.hit(&scattered_ray, (1e-5, f64::INFINITY)).unwrap();
+            let hit_light = light_hit.light.expect("The ray should hit the light");
+            let scatter_weight = power_heuristic(
+                scatter_pdf,
+                lights.object_pdf(hit_light, scattered_ray.origin, scattered_ray.direction),
+            );
+
+            assert!(
+                (light_weight + scatter_weight - 1.).abs() < 1e-9,
+                "The weights for {direction:?} are {light_weight} and {scatter_weight}"
+            );
+        }
+    }
+
+    #[test]
+    fn sampling_the_lights_gives_the_same_colour_as_not_sampling_them() {
+        let scene = plane_under_light();
+        let lights = Lights::new(&scene, &Colour::ZERO);
+        let empty_scene = Scene::new();
+        let no_lights = Lights::new(&empty_scene, &Colour::ZERO);
+
+        let mean_colour = |lights: &Lights| {
+            let samples = 1 << 16;
+            (0..samples)
+                .map(|i| {
+                    let mut sampler = SamplerKind::Sobol.create(0, (0, 0), i, samples);
+                    camera_ray()
+                        .trace(&scene, lights, 4, 4, &mut sampler)
+                        .colour
+                })
+                .fold(Colour::ZERO, |sum, colour| sum + colour)
+                / samples as f64
+        };
+
+        // A diffuse surface lit by a sphere which is completely above it gets an irradiance of
+        // π L (r / d)², and reflects ρ / π of it
+        let expected = ALBEDO * RADIANCE * (LIGHT_RADIUS / LIGHT_HEIGHT).powi(2);
+        for (name, lights) in [("with", &lights), ("without", &no_lights)] {
+            let colour = mean_colour(lights);
+            assert!(
+                (colour.x - expected).abs() < 0.005,
+                "The mean colour {name} sampling the lights is {colour:?} rather than {expected}"
             );
-            (1. - height) * v!(1) + height * v!(0.5, 0.7, 1)
         }
     }
 }
@@ -0,0 +1,1210 @@
+//! This module provides the [`Renderer`] type, which is the main entry point for rendering a
+//! scene.
+
+use crate::{
+    aov::Aov,
+    camera::Camera,
+    checkpoint::{Checkpoint, CheckpointOpts},
+    denoise::{denoise, DenoiseOpts},
+    environment::{Gradient, SharedEnvironment},
+    film::{Film, FilmSample, PixelStatistics},
+    filter::Filter,
+    framebuffer::Framebuffer,
+    object::{Lights, Object},
+    ray::{PathEnd, PathStats},
+    sampler::{Sampler, SamplerKind},
+    tile::{Region, TileOrder},
+    vector::{v, Colour, Point, Vec3},
+};
+use color_eyre::{eyre::ensure, Result};
+use image::Rgb32FImage;
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator,
+    IntoParallelRefMutIterator, ParallelIterator,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, mem,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often to publish the image while rendering tiles, since there are usually far more tiles
+/// than passes.
+const TILE_PUBLISH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The options needed to render a scene.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RenderOpts {
+    /// The full width of the image.
+    pub width: u32,
+
+    /// The full height of the image.
+    pub height: u32,
+
+    /// How many samples to take for each pixel. With adaptive sampling, this is how many
+    /// samples each pixel gets before we start checking whether it has converged.
+    pub samples: u16,
+
+    /// The most samples that any pixel can get with adaptive sampling. This is ignored without
+    /// adaptive sampling.
+    pub max_samples: u16,
+
+    /// The noise threshold for adaptive sampling. If this is set, then each pixel stops getting
+    /// samples once its relative error falls below this, so that smooth areas of the image don't
+    /// waste time on samples that they don't need.
+    pub noise_threshold: Option<f64>,
+
+    /// The maximum number of times each path can bounce.
+    pub bounces: u16,
+
+    /// How many times each path bounces before it can be terminated by Russian roulette.
+    pub min_bounces: u16,
+
+    /// The sampler which decides the random numbers used for each sample.
+    pub sampler: SamplerKind,
+
+    /// The filter used to splat each sample onto the pixels around it.
+    pub filter: Filter,
+
+    /// The most time that the render can take. The render stops before starting a pass that
+    /// probably wouldn't finish in time, but it always takes at least one pass. When rendering
+    /// tiles, every tile which has started gets at least one pass, but tiles which haven't
+    /// started by the time limit don't get any samples.
+    pub time_limit: Option<Duration>,
+
+    /// The part of the image to render. If this isn't set, then we render the whole image. The
+    /// rest of the image is left black, so renders of different regions can be put together
+    /// afterwards.
+    pub region: Option<Region>,
+
+    /// The size of the tiles to split the image into. If this is set, then each tile gets all of
+    /// its samples before we move on to the next one, rather than taking one pass over the whole
+    /// image at a time. This is faster since each tile stays in the cache, and finished tiles
+    /// can be looked at while the rest of the image is still rendering.
+    pub tile_size: Option<u32>,
+
+    /// The order to render the tiles in.
+    pub tile_order: TileOrder,
+
+    /// The extra passes to render alongside the image. See [`Renderer::render_aovs`].
+    pub aovs: Vec<Aov>,
+
+    /// If this is set, then the final image gets denoised with these options. See
+    /// [`Renderer::finish`].
+    pub denoise: Option<DenoiseOpts>,
+
+    /// The seed for all the random numbers used while rendering. Rendering the same scene with
+    /// the same options and seed always gives exactly the same image.
+    pub seed: u64,
+}
+
+impl RenderOpts {
+    /// The noise threshold for adaptive sampling once a pixel has had the given number of
+    /// samples. Every pixel gets at least the normal number of samples before we decide that it
+    /// has converged, so that we have a decent estimate of its noise.
+    fn noise_threshold_after(&self, samples_done: u16) -> Option<f64> {
+        self.noise_threshold
+            .filter(|_| samples_done >= self.samples)
+    }
+}
+
+impl Default for RenderOpts {
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 1080,
+            samples: 100,
+            max_samples: 400,
+            noise_threshold: None,
+            bounces: 50,
+            min_bounces: 3,
+            sampler: SamplerKind::default(),
+            filter: Filter::default(),
+            time_limit: None,
+            region: None,
+            tile_size: None,
+            tile_order: TileOrder::default(),
+            aovs: vec![],
+            denoise: None,
+            seed: 0,
+        }
+    }
+}
+
+/// Information about how far along a render is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+    /// How many passes of samples have been taken so far. Every pixel which hasn't converged
+    /// gets one sample in each pass. When rendering tiles, this is the fewest passes of any tile.
+    pub samples_done: u16,
+
+    /// The most passes that will be taken in total. With adaptive sampling, the render can
+    /// finish before this if every pixel converges.
+    pub total_samples: u16,
+
+    /// How many pixels have converged and stopped getting samples.
+    pub converged_pixels: u32,
+
+    /// How many pixels there are in total.
+    pub total_pixels: u32,
+
+    /// How many tiles have been finished. This is always zero without tiles.
+    pub tiles_done: u32,
+
+    /// How many tiles there are in total. This is always zero without tiles.
+    pub total_tiles: u32,
+
+    /// How long the render has been going.
+    pub elapsed: Duration,
+
+    /// Statistics about all the paths traced so far.
+    pub stats: RenderStats,
+}
+
+/// Statistics about the paths traced during a render.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RenderStats {
+    /// How many paths have been traced.
+    pub paths: u64,
+
+    /// The total number of bounces of all the paths.
+    pub bounces: u64,
+
+    /// The total number of rays cast into the scene, including shadow rays.
+    pub rays: u64,
+
+    /// The most bounces of any single path.
+    pub longest_path: u16,
+
+    /// How many paths escaped to the sky.
+    pub escaped: u64,
+
+    /// How many paths were absorbed by a material.
+    pub absorbed: u64,
+
+    /// How many paths were terminated by Russian roulette.
+    pub russian_roulette: u64,
+
+    /// How many paths reached the maximum number of bounces.
+    pub max_bounces: u64,
+}
+
+impl RenderStats {
+    /// Record a single path.
+    fn record(&mut self, path: PathStats) {
+        self.paths += 1;
+        self.bounces += path.bounces as u64;
+        self.rays += path.rays as u64;
+        self.longest_path = self.longest_path.max(path.bounces);
+
+        match path.end {
+            PathEnd::Escaped => self.escaped += 1,
+            PathEnd::Absorbed => self.absorbed += 1,
+            PathEnd::RussianRoulette => self.russian_roulette += 1,
+            PathEnd::MaxBounces => self.max_bounces += 1,
+        }
+    }
+
+    /// Combine the statistics from two sets of paths.
+    fn merge(self, other: Self) -> Self {
+        Self {
+            paths: self.paths + other.paths,
+            bounces: self.bounces + other.bounces,
+            rays: self.rays + other.rays,
+            longest_path: self.longest_path.max(other.longest_path),
+            escaped: self.escaped + other.escaped,
+            absorbed: self.absorbed + other.absorbed,
+            russian_roulette: self.russian_roulette + other.russian_roulette,
+            max_bounces: self.max_bounces + other.max_bounces,
+        }
+    }
+
+    /// How many rays were cast per second, given how long it took to cast them all.
+    pub fn rays_per_second(&self, elapsed: Duration) -> f64 {
+        self.rays as f64 / elapsed.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+
+    /// The average number of bounces per path.
+    pub fn mean_bounces(&self) -> f64 {
+        if self.paths == 0 {
+            0.
+        } else {
+            self.bounces as f64 / self.paths as f64
+        }
+    }
+}
+
+/// Where a ray through the centre of a pixel and the centre of the lens hit the scene.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CentreHit {
+    /// How far the ray went before it hit something.
+    pub depth: f64,
+
+    /// The point that the ray hit.
+    pub position: Point,
+
+    /// The surface normal where the ray hit, facing the camera.
+    pub normal: Vec3,
+
+    /// The ID of the object that the ray hit, if it has one.
+    pub object_id: Option<u32>,
+
+    /// The address of the material that the ray hit, which tells materials apart.
+    pub material: usize,
+
+    /// The albedo of the material where the ray hit.
+    pub albedo: Colour,
+}
+
+impl CentreHit {
+    /// Cast a ray through the centre of the given pixel of an image with the given size, and
+    /// find where it hits the scene, if it hits anything.
+    pub fn trace(
+        scene: &impl Object,
+        camera: &Camera,
+        (width, height): (u32, u32),
+        (x, y): (u32, u32),
+    ) -> Option<Self> {
+        let ray = camera.get_ray(
+            (x as f64 + 0.5) / width as f64,
+            (y as f64 + 0.5) / height as f64,
+            (0.5, 0.5),
+        );
+
+        scene.hit(&ray, (1e-5, f64::INFINITY)).map(|hit| Self {
+            depth: hit.t,
+            position: hit.intersection_point,
+            normal: hit.surface_normal,
+            object_id: hit.object_id,
+            // Materials don't have IDs, so we tell them apart by their address
+            material: hit.material.address() as usize,
+            albedo: hit.material.albedo(&hit),
+        })
+    }
+}
+
+/// A handle which can be used to cancel a render from another thread.
+///
+/// Cancelling a render lets it finish the current pass, and then the render returns the image
+/// with all the samples taken so far. Abandoning a render cancels it in the same way, but also
+/// says that nothing is going to use those samples.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    /// Whether this token has been cancelled itself.
+    cancelled: Arc<AtomicBool>,
+
+    /// Whether this token has been abandoned itself.
+    abandoned: Arc<AtomicBool>,
+
+    /// The token that this one was made from, which cancels this one too.
+    parent: Option<Box<CancelToken>>,
+}
+
+impl CancelToken {
+    /// Create a new token which hasn't been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new token which gets cancelled whenever this one does, but which can also be
+    /// cancelled on its own without cancelling this one.
+    pub fn child(&self) -> Self {
+        Self {
+            cancelled: Arc::default(),
+            abandoned: Arc::default(),
+            parent: Some(Box::new(self.clone())),
+        }
+    }
+
+    /// Cancel the render.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Cancel the render and throw away its samples. The render doesn't make any more
+    /// checkpoints after this, so that it can't overwrite a checkpoint that's still wanted.
+    pub fn abandon(&self) {
+        self.abandoned.store(true, Ordering::Relaxed);
+        self.cancel();
+    }
+
+    /// Has the render been cancelled?
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|parent| parent.is_cancelled())
+    }
+
+    /// Has the render been abandoned?
+    pub fn is_abandoned(&self) -> bool {
+        self.abandoned.load(Ordering::Relaxed)
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|parent| parent.is_abandoned())
+    }
+}
+
+/// A callback which gets told about the progress of the render after every pass.
+type ProgressCallback = Box<dyn Fn(&Progress) + Send + Sync>;
+
+/// A callback which gets given a checkpoint of the render every so often.
+type CheckpointCallback = Box<dyn Fn(&Checkpoint) + Send + Sync>;
+
+/// Something that renders scenes.
+///
+/// The renderer publishes the image to its [`Framebuffer`] after every pass, so other threads can
+/// look at the image while it's being rendered.
+pub struct Renderer {
+    /// The options for rendering.
+    opts: RenderOpts,
+
+    /// The framebuffer that we publish the image to.
+    framebuffer: Arc<Framebuffer>,
+
+    /// The token that tells us when to stop.
+    cancel_token: CancelToken,
+
+    /// The environment surrounding the scene.
+    environment: SharedEnvironment,
+
+    /// An optional callback to report progress to.
+    progress_callback: Option<ProgressCallback>,
+
+    /// An optional callback to give checkpoints to, along with the options for making them.
+    checkpoints: Option<(CheckpointOpts, CheckpointCallback)>,
+
+    /// A checkpoint of an earlier render to carry on from.
+    resume_from: Option<Checkpoint>,
+}
+
+impl fmt::Debug for Renderer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Renderer")
+            .field("opts", &self.opts)
+            .field("framebuffer", &self.framebuffer)
+            .field("cancel_token", &self.cancel_token)
+            .field(
+                "checkpoint_opts",
+                &self.checkpoints.as_ref().map(|(opts, _)| opts),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+impl Renderer {
+    /// Create a new renderer with the given options.
+    pub fn new(opts: RenderOpts) -> Self {
+        Self {
+            framebuffer: Arc::new(Framebuffer::new(opts.width, opts.height)),
+            opts,
+            cancel_token: CancelToken::new(),
+            environment: Arc::new(Gradient::default()),
+            progress_callback: None,
+            checkpoints: None,
+            resume_from: None,
+        }
+    }
+
+    /// Call the given function after every pass to report progress.
+    pub fn with_progress_callback(
+        mut self,
+        callback: impl Fn(&Progress) + Send + Sync + 'static,
+    ) -> Self {
+        self.progress_callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Make a checkpoint of the render every so often and give it to the given function, which
+    /// will usually save it to disk. A final checkpoint is always made at the end of the render,
+    /// so that more samples can be added later, unless the render gets abandoned.
+    pub fn with_checkpoints(
+        mut self,
+        opts: CheckpointOpts,
+        callback: impl Fn(&Checkpoint) + Send + Sync + 'static,
+    ) -> Self {
+        self.checkpoints = Some((opts, Box::new(callback)));
+        self
+    }
+
+    /// Carry on rendering from the given checkpoint, rather than starting from scratch.
+    ///
+    /// The samples and passes in the options for this renderer can be different to the ones in
+    /// the checkpoint, which lets us add more samples to a finished render. But anything which
+    /// changes where the samples go or how they get weighted must be the same.
+    pub fn resume_from(mut self, checkpoint: Checkpoint) -> Result<Self> {
+        let (old, new) = (&checkpoint.opts, &self.opts);
+        ensure!(
+            (old.width, old.height) == (new.width, new.height),
+            "The checkpoint is {}x{}, but the render is {}x{}",
+            old.width,
+            old.height,
+            new.width,
+            new.height
+        );
+        ensure!(
+            old.filter == new.filter,
+            "The checkpoint uses the filter {:?}, but the render uses {:?}",
+            old.filter,
+            new.filter
+        );
+        ensure!(
+            old.sampler == new.sampler,
+            "The checkpoint uses the {:?} sampler, but the render uses {:?}",
+            old.sampler,
+            new.sampler
+        );
+        ensure!(
+            old.seed == new.seed,
+            "The checkpoint uses the seed {}, but the render uses {}",
+            old.seed,
+            new.seed
+        );
+        ensure!(
+            old.region == new.region,
+            "The checkpoint renders the region {:?}, but the render uses {:?}",
+            old.region,
+            new.region
+        );
+        ensure!(
+            (old.tile_size, old.tile_order) == (new.tile_size, new.tile_order),
+            "The checkpoint uses tiles of size {:?} in {:?} order, but the render uses {:?} in \
+             {:?} order",
+            old.tile_size,
+            old.tile_order,
+            new.tile_size,
+            new.tile_order
+        );
+
+        for &aov in &new.aovs {
+            ensure!(
+                !aov.is_accumulated() || checkpoint.film.collects(aov),
+                "The checkpoint didn't collect the {} pass, so it can't be added now",
+                aov.name()
+            );
+        }
+
+        self.resume_from = Some(checkpoint);
+        Ok(self)
+    }
+
+    /// Use the given token to cancel the render.
+    pub fn with_cancel_token(mut self, cancel_token: CancelToken) -> Self {
+        self.cancel_token = cancel_token;
+        self
+    }
+
+    /// Publish the image to the given framebuffer rather than a new one, so that something
+    /// watching it can keep watching across several renders. The framebuffer must be the same
+    /// size as the image.
+    pub fn with_framebuffer(mut self, framebuffer: Arc<Framebuffer>) -> Self {
+        self.framebuffer = framebuffer;
+        self
+    }
+
+    /// Use the given environment to light the scene. By default, this is a simple blue sky.
+    pub fn with_environment(mut self, environment: SharedEnvironment) -> Self {
+        self.environment = environment;
+        self
+    }
+
+    /// The options for this renderer.
+    pub fn opts(&self) -> &RenderOpts {
+        &self.opts
+    }
+
+    /// The framebuffer which this renderer publishes its image to.
+    pub fn framebuffer(&self) -> Arc<Framebuffer> {
+        Arc::clone(&self.framebuffer)
+    }
+
+    /// The token which can be used to cancel this render.
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel_token.clone()
+    }
+
+    /// Render the scene as seen by the given camera, and return the linear float image.
+    ///
+    /// If the render gets cancelled, then this returns early with all the samples taken so far.
+    pub fn render(&self, scene: &impl Object, camera: &Camera) -> Rgb32FImage {
+        self.finish(&self.render_to_film(scene, camera).0)
+    }
+
+    /// Turn a film from [`Renderer::render_to_film`] into the final image, which means
+    /// denoising it if the options ask for that. A denoised image also gets published to the
+    /// framebuffer, so that it shows up in the preview.
+    pub fn finish(&self, film: &Film) -> Rgb32FImage {
+        match &self.opts.denoise {
+            Some(opts) => {
+                let image = denoise(film, opts);
+                self.framebuffer.publish(&image);
+                image
+            }
+            None => film.to_image(),
+        }
+    }
+
+    /// Make an image for each of the extra passes in the options, in the same order, using the
+    /// film from [`Renderer::render_to_film`] for the passes which get collected from every
+    /// sample. See [`Aov`] for what each pass holds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the film doesn't collect one of the passes, which can't happen if it was
+    /// rendered by this renderer.
+    pub fn render_aovs(
+        &self,
+        scene: &impl Object,
+        camera: &Camera,
+        film: &Film,
+    ) -> Vec<(Aov, Rgb32FImage)> {
+        let (width, height) = (self.opts.width, self.opts.height);
+        let region = film.region();
+
+        // The passes which can't be averaged come from a single ray through the centre of each
+        // pixel and the centre of the lens
+        let hits: Vec<Option<CentreHit>> = if self.opts.aovs.iter().any(|aov| !aov.is_accumulated())
+        {
+            (0..region.pixel_count())
+                .into_par_iter()
+                .map(|index| CentreHit::trace(scene, camera, (width, height), region.pixel(index)))
+                .collect()
+        } else {
+            vec![]
+        };
+
+        let mut material_ids = HashMap::new();
+        let material_ids: Vec<u32> = hits
+            .iter()
+            .map(|hit| match hit {
+                Some(hit) => {
+                    let next_id = material_ids.len() as u32 + 1;
+                    *material_ids.entry(hit.material).or_insert(next_id)
+                }
+                None => 0,
+            })
+            .collect();
+
+        let hit_image = |colour: &(dyn Fn(usize) -> Colour + Sync)| {
+            Rgb32FImage::from_fn(width, height, |x, y| {
+                if region.contains(x, y) {
+                    colour(region.index(x, y)).into()
+                } else {
+                    Colour::ZERO.into()
+                }
+            })
+        };
+
+        self.opts
+            .aovs
+            .iter()
+            .map(|&aov| {
+                let image = match aov {
+                    Aov::Depth => hit_image(&|index| {
+                        v!(hits[index].as_ref().map_or(f64::INFINITY, |hit| hit.depth))
+                    }),
+                    Aov::Position => hit_image(&|index| {
+                        hits[index]
+                            .as_ref()
+                            .map_or(Colour::ZERO, |hit| hit.position)
+                    }),
+                    Aov::ObjectId => hit_image(&|index| {
+                        let object_id = hits[index].as_ref().and_then(|hit| hit.object_id);
+                        v!(object_id.map_or(0., |id| id as f64 + 1.))
+                    }),
+                    Aov::MaterialId => hit_image(&|index| v!(material_ids[index] as f64)),
+                    Aov::Albedo | Aov::Normal | Aov::Direct | Aov::Indirect | Aov::SampleCount => {
+                        film.aov_image(aov)
+                            .unwrap_or_else(|| panic!("The film should collect the {aov:?} pass"))
+                    }
+                };
+                (aov, image)
+            })
+            .collect()
+    }
+
+    /// Render the scene like [`Renderer::render`], but return the [`Film`] with all the
+    /// samples, along with statistics about all the paths that were traced.
+    pub fn render_to_film(&self, scene: &impl Object, camera: &Camera) -> (Film, RenderStats) {
+        let RenderOpts {
+            width,
+            height,
+            samples,
+            max_samples,
+            noise_threshold,
+            bounces,
+            min_bounces,
+            sampler,
+            filter,
+            region,
+            tile_size,
+            tile_order,
+            seed,
+            ..
+        } = self.opts;
+        let region = region.unwrap_or_else(|| Region::full(width, height));
+
+        // With adaptive sampling, we keep going until every pixel has converged or has reached
+        // the maximum number of samples
+        let passes = match noise_threshold {
+            Some(_) => samples.max(max_samples),
+            None => samples,
+        };
+        let tiles = tile_size.map(|tile_size| region.tiles(tile_size, tile_order));
+
+        let mut checkpoint = match &self.resume_from {
+            Some(checkpoint) => Checkpoint {
+                opts: self.opts.clone(),
+                ..checkpoint.clone()
+            },
+            None => Checkpoint {
+                arguments: vec![],
+                opts: self.opts.clone(),
+                samples_done: 0,
+                sampler_sample_count: samples as u32,
+                tile_passes: vec![0; tiles.as_ref().map_or(0, Vec::len)],
+                film: Film::new(width, height, region, filter)
+                    .with_aovs(&self.opts.aovs)
+                    .with_aovs(match self.opts.denoise {
+                        Some(_) => &[Aov::Albedo, Aov::Normal],
+                        None => &[],
+                    }),
+                stats: RenderStats::default(),
+            },
+        };
+        if let Some((opts, _)) = &self.checkpoints {
+            checkpoint.arguments.clone_from(&opts.arguments);
+        }
+
+        let lights = Lights::new(scene, &*self.environment);
+        let sampler_sample_count = checkpoint.sampler_sample_count;
+        let trace_pixel = |(i, j): (u32, u32), sample_idx: u16| {
+            let mut sampler = sampler.create(seed, (i, j), sample_idx as u32, sampler_sample_count);
+            let (offset_x, offset_y) = sampler.next_2d();
+            let lens_sample = sampler.next_2d();
+
+            let position = (i as f64 + offset_x, j as f64 + offset_y);
+
+            let path = camera
+                .get_ray(
+                    position.0 / width as f64,
+                    position.1 / height as f64,
+                    lens_sample,
+                )
+                .trace(scene, &lights, bounces, min_bounces, &mut sampler);
+
+            let sample = FilmSample {
+                position,
+                colour: path.colour,
+                direct: path.direct,
+                albedo: path.albedo,
+                normal: path.normal,
+            };
+            (sample, path.stats)
+        };
+
+        self.framebuffer.publish(&checkpoint.film.to_image());
+        match tiles {
+            Some(tiles) => self.render_tiles(&mut checkpoint, &tiles, passes, trace_pixel),
+            None => self.render_passes(&mut checkpoint, passes, trace_pixel),
+        }
+        self.framebuffer.publish(&checkpoint.film.to_image());
+
+        if let Some((_, callback)) = self.checkpoint_callback() {
+            callback(&checkpoint);
+        }
+
+        (checkpoint.film, checkpoint.stats)
+    }
+
+    /// The options and callback for making checkpoints, unless there aren't any or the render
+    /// has been abandoned.
+    fn checkpoint_callback(&self) -> Option<(&CheckpointOpts, &CheckpointCallback)> {
+        match &self.checkpoints {
+            Some((opts, callback)) if !self.cancel_token.is_abandoned() => Some((opts, callback)),
+            _ => None,
+        }
+    }
+
+    /// Render the region of the film in the checkpoint one pass at a time, where each pass takes
+    /// a sample for every pixel which hasn't converged yet.
+    fn render_passes(
+        &self,
+        checkpoint: &mut Checkpoint,
+        passes: u16,
+        trace_pixel: impl Fn((u32, u32), u16) -> (FilmSample, PathStats) + Sync,
+    ) {
+        let region = checkpoint.film.region();
+        let pixel_count = region.pixel_count();
+        let mut pass_samples = vec![None; pixel_count];
+        let mut active_pixels = vec![true; pixel_count];
+
+        let check_convergence = |film: &Film, active_pixels: &mut [bool], samples_done| match self
+            .opts
+            .noise_threshold_after(samples_done)
+        {
+            Some(threshold) => update_active_pixels(
+                |x, y| film.statistics(x, y),
+                region,
+                threshold,
+                active_pixels,
+            ),
+            None => 0,
+        };
+        let mut converged_pixels = check_convergence(
+            &checkpoint.film,
+            &mut active_pixels,
+            checkpoint.samples_done,
+        );
+
+        let start_time = Instant::now();
+        let mut last_pass_time = Duration::ZERO;
+        let mut last_checkpoint_time = Instant::now();
+
+        for sample_idx in checkpoint.samples_done..passes {
+            if self.cancel_token.is_cancelled() || converged_pixels == pixel_count {
+                break;
+            }
+
+            // We assume that the next pass will take about as long as the last one
+            let out_of_time = self
+                .opts
+                .time_limit
+                .is_some_and(|limit| start_time.elapsed() + last_pass_time > limit);
+            if sample_idx > checkpoint.samples_done && out_of_time {
+                break;
+            }
+
+            let pass_start_time = Instant::now();
+            let pass_stats = pass_samples
+                .par_iter_mut()
+                .zip(active_pixels.par_iter())
+                .enumerate()
+                .fold(
+                    RenderStats::default,
+                    |mut pass_stats, (index, (film_sample, &active))| {
+                        *film_sample = active.then(|| {
+                            let (sample, path_stats) = trace_pixel(region.pixel(index), sample_idx);
+                            pass_stats.record(path_stats);
+                            sample
+                        });
+                        pass_stats
+                    },
+                )
+                .reduce(RenderStats::default, RenderStats::merge);
+
+            checkpoint.stats = checkpoint.stats.merge(pass_stats);
+            checkpoint.film.add_pass(region, &pass_samples);
+            checkpoint.samples_done = sample_idx + 1;
+            converged_pixels = check_convergence(
+                &checkpoint.film,
+                &mut active_pixels,
+                checkpoint.samples_done,
+            );
+
+            self.framebuffer.publish(&checkpoint.film.to_image());
+            last_pass_time = pass_start_time.elapsed();
+
+            if let Some(callback) = &self.progress_callback {
+                callback(&Progress {
+                    samples_done: checkpoint.samples_done,
+                    total_samples: passes,
+                    converged_pixels: converged_pixels as u32,
+                    total_pixels: pixel_count as u32,
+                    tiles_done: 0,
+                    total_tiles: 0,
+                    elapsed: start_time.elapsed(),
+                    stats: checkpoint.stats,
+                });
+            }
+
+            if let Some((opts, callback)) = self.checkpoint_callback() {
+                if last_checkpoint_time.elapsed() >= opts.interval {
+                    callback(checkpoint);
+                    last_checkpoint_time = Instant::now();
+                }
+            }
+        }
+    }
+
+    /// Render the given tiles one at a time on each thread, where each tile gets all of its
+    /// passes before the thread moves on to the next tile.
+    ///
+    /// Each tile gets rendered to its own film, which covers the tile and the pixels around it
+    /// that its samples get splatted onto, and then gets added to the film in the checkpoint
+    /// once it and every tile before it are done.
+    fn render_tiles(
+        &self,
+        checkpoint: &mut Checkpoint,
+        tiles: &[Region],
+        passes: u16,
+        trace_pixel: impl Fn((u32, u32), u16) -> (FilmSample, PathStats) + Sync,
+    ) {
+        let time_limit = self.opts.time_limit;
+        let region = checkpoint.film.region();
+        let reach = checkpoint.film.filter_reach();
+
+        let tiles_done = checkpoint
+            .tile_passes
+            .iter()
+            .filter(|&&tile_passes| tile_passes >= passes)
+            .count();
+        let shared = Mutex::new(TileProgress {
+            checkpoint,
+            finished_tiles: BTreeMap::new(),
+            next_tile_to_merge: 0,
+            tiles_done,
+            converged_pixels: 0,
+            last_publish_time: Instant::now(),
+            last_checkpoint_time: Instant::now(),
+        });
+        let next_tile = AtomicUsize::new(0);
+        let start_time = Instant::now();
+
+        let render_tile = |index: usize, tile: Region| {
+            let (first_pass, base_statistics, mut film) = {
+                let shared = shared
+                    .lock()
+                    .expect("No thread should panic while rendering");
+                let first_pass = shared.checkpoint.tile_passes[index];
+                if first_pass >= passes {
+                    return;
+                }
+
+                // A resumed tile might already have some samples, which count towards whether
+                // its pixels have converged
+                let base_statistics: Vec<PixelStatistics> = (0..tile.pixel_count())
+                    .map(|index| {
+                        let (x, y) = tile.pixel(index);
+                        shared.checkpoint.film.statistics(x, y)
+                    })
+                    .collect();
+                let film = shared.checkpoint.film.new_like(tile.expand(reach, &region));
+                (first_pass, base_statistics, film)
+            };
+
+            let mut tile_samples = vec![None; tile.pixel_count()];
+            let mut active_pixels = vec![true; tile.pixel_count()];
+            let mut tile_stats = RenderStats::default();
+
+            let check_convergence =
+                |film: &Film, active_pixels: &mut [bool], samples_done| match self
+                    .opts
+                    .noise_threshold_after(samples_done)
+                {
+                    Some(threshold) => update_active_pixels(
+                        |x, y| base_statistics[tile.index(x, y)].merge(film.statistics(x, y)),
+                        tile,
+                        threshold,
+                        active_pixels,
+                    ),
+                    None => 0,
+                };
+            let mut converged_pixels = check_convergence(&film, &mut active_pixels, first_pass);
+
+            let mut samples_done = first_pass;
+            let mut last_pass_time = Duration::ZERO;
+            while samples_done < passes && converged_pixels < tile.pixel_count() {
+                let out_of_time =
+                    time_limit.is_some_and(|limit| start_time.elapsed() + last_pass_time > limit);
+                if self.cancel_token.is_cancelled() || (samples_done > first_pass && out_of_time) {
+                    break;
+                }
+
+                let pass_start_time = Instant::now();
+                for (index, (film_sample, &active)) in
+                    tile_samples.iter_mut().zip(&active_pixels).enumerate()
+                {
+                    *film_sample = active.then(|| {
+                        let (sample, path_stats) = trace_pixel(tile.pixel(index), samples_done);
+                        tile_stats.record(path_stats);
+                        sample
+                    });
+                }
+
+                film.add_pass(tile, &tile_samples);
+                samples_done += 1;
+                converged_pixels = check_convergence(&film, &mut active_pixels, samples_done);
+                last_pass_time = pass_start_time.elapsed();
+            }
+
+            let mut shared = shared
+                .lock()
+                .expect("No thread should panic while rendering");
+            shared.finished_tiles.insert(
+                index,
+                FinishedTile {
+                    film,
+                    stats: tile_stats,
+                    samples_done,
+                },
+            );
+            shared.merge_finished_tiles(passes);
+
+            if samples_done >= passes || converged_pixels == tile.pixel_count() {
+                shared.tiles_done += 1;
+                shared.converged_pixels += converged_pixels;
+            }
+
+            if shared.last_publish_time.elapsed() >= TILE_PUBLISH_INTERVAL {
+                self.framebuffer.publish(&shared.checkpoint.film.to_image());
+                shared.last_publish_time = Instant::now();
+            }
+
+            if let Some(callback) = &self.progress_callback {
+                callback(&Progress {
+                    samples_done: shared.checkpoint.samples_done,
+                    total_samples: passes,
+                    converged_pixels: shared.converged_pixels as u32,
+                    total_pixels: region.pixel_count() as u32,
+                    tiles_done: shared.tiles_done as u32,
+                    total_tiles: tiles.len() as u32,
+                    elapsed: start_time.elapsed(),
+                    stats: shared.checkpoint.stats,
+                });
+            }
+
+            if let Some((opts, callback)) = self.checkpoint_callback() {
+                if shared.last_checkpoint_time.elapsed() >= opts.interval {
+                    callback(shared.checkpoint);
+                    shared.last_checkpoint_time = Instant::now();
+                }
+            }
+        };
+
+        // Each thread takes the next tile in order whenever it finishes one. These are plain
+        // threads rather than rayon tasks, since they hold a lock while publishing the image,
+        // which uses rayon itself
+        thread::scope(|scope| {
+            for _ in 0..rayon::current_num_threads() {
+                scope.spawn(|| loop {
+                    let out_of_time = time_limit.is_some_and(|limit| start_time.elapsed() > limit);
+                    if self.cancel_token.is_cancelled() || out_of_time {
+                        break;
+                    }
+
+                    let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                    match tiles.get(index) {
+                        Some(&tile) => render_tile(index, tile),
+                        None => break,
+                    }
+                });
+            }
+        });
+
+        shared
+            .into_inner()
+            .expect("No thread should panic while rendering")
+            .merge_remaining_tiles();
+    }
+}
+
+/// The progress of a render with tiles, which is shared between all the threads rendering tiles.
+struct TileProgress<'a> {
+    /// The checkpoint that every finished tile gets added to.
+    checkpoint: &'a mut Checkpoint,
+
+    /// The tiles which have finished but haven't been added to the checkpoint yet, because a
+    /// tile before them is still rendering.
+    ///
+    /// Neighbouring tiles splat samples onto some of the same pixels, and adding up floats in a
+    /// different order gives a slightly different result. So tiles always get added in order,
+    /// which means that the image doesn't depend on which thread finishes first.
+    finished_tiles: BTreeMap<usize, FinishedTile>,
+
+    /// The index of the next tile to add to the checkpoint.
+    next_tile_to_merge: usize,
+
+    /// How many tiles have been finished.
+    tiles_done: usize,
+
+    /// How many pixels in the finished tiles have converged.
+    converged_pixels: usize,
+
+    /// When the image was last published to the framebuffer.
+    last_publish_time: Instant,
+
+    /// When the last checkpoint was made.
+    last_checkpoint_time: Instant,
+}
+
+impl TileProgress<'_> {
+    /// Add the finished tiles to the checkpoint in order, until we reach a tile which hasn't
+    /// finished yet. Tiles which already had all of the given number of passes when the render
+    /// started have nothing to add, so they get skipped.
+    fn merge_finished_tiles(&mut self, passes: u16) {
+        loop {
+            let index = self.next_tile_to_merge;
+            if let Some(tile) = self.finished_tiles.remove(&index) {
+                self.merge_tile(index, tile);
+            } else if self
+                .checkpoint
+                .tile_passes
+                .get(index)
+                .is_none_or(|&tile_passes| tile_passes < passes)
+            {
+                break;
+            }
+            self.next_tile_to_merge += 1;
+        }
+    }
+
+    /// Add every finished tile which is still waiting to the checkpoint, in order. This is only
+    /// needed if the render stopped early, since then some tiles never finish.
+    fn merge_remaining_tiles(mut self) {
+        for (index, tile) in mem::take(&mut self.finished_tiles) {
+            self.merge_tile(index, tile);
+        }
+    }
+
+    /// Add a finished tile with the given index to the checkpoint.
+    fn merge_tile(&mut self, index: usize, tile: FinishedTile) {
+        let checkpoint = &mut *self.checkpoint;
+        checkpoint.film.merge(&tile.film);
+        checkpoint.stats = checkpoint.stats.merge(tile.stats);
+        checkpoint.tile_passes[index] = tile.samples_done;
+        checkpoint.samples_done = checkpoint.tile_passes.iter().copied().min().unwrap_or(0);
+    }
+}
+
+/// A tile which has finished rendering, but might not have been added to the checkpoint yet.
+struct FinishedTile {
+    /// The film with all the samples for the tile.
+    film: Film,
+
+    /// Statistics about the paths traced for the tile.
+    stats: RenderStats,
+
+    /// How many passes the tile has had in total, including any from before the render was
+    /// resumed.
+    samples_done: u16,
+}
+
+/// Stop sampling every pixel in the given region which has converged below the given noise
+/// threshold, where the active pixels are in row-major order, and return how many pixels have
+/// converged.
+///
+/// A single pixel can look converged just by luck, when it hasn't found any of the rare paths
+/// which make it brighter yet. So each pixel only converges once every pixel around it in the
+/// region is below the threshold too.
+fn update_active_pixels(
+    statistics: impl Fn(u32, u32) -> PixelStatistics + Sync,
+    region: Region,
+    threshold: f64,
+    active_pixels: &mut [bool],
+) -> usize {
+    let below_threshold: Vec<bool> = (0..region.pixel_count())
+        .into_par_iter()
+        .map(|index| {
+            let (x, y) = region.pixel(index);
+            statistics(x, y).relative_error() < threshold
+        })
+        .collect();
+
+    active_pixels
+        .par_iter_mut()
+        .enumerate()
+        .for_each(|(index, active)| {
+            let (x, y) = region.pixel(index);
+            let neighbourhood = Region::new(x, y, 1, 1).expand(1, &region);
+            let neighbourhood_converged = (neighbourhood.y..neighbourhood.bottom()).all(|j| {
+                (neighbourhood.x..neighbourhood.right())
+                    .all(|i| below_threshold[region.index(i, j)])
+            });
+
+            if neighbourhood_converged {
+                *active = false;
+            }
+        });
+
+    active_pixels.par_iter().filter(|active| !**active).count()
+}
+
+/// Render the scene as seen by the given camera with the given options, and return the linear
+/// float image.
+///
+/// Use a [`Renderer`] if you want to report progress or cancel the render.
+pub fn render(scene: &impl Object, camera: &Camera, opts: RenderOpts) -> Rgb32FImage {
+    Renderer::new(opts).render(scene, camera)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The statistics of a pixel which has had the given number of samples, half of them with
+    /// each of the given luminances.
+    fn statistics(count: u32, (a, b): (f64, f64)) -> PixelStatistics {
+        let half = count as f64 / 2.;
+        PixelStatistics {
+            count,
+            luminance_sum: half * (a + b),
+            luminance_square_sum: half * (a * a + b * b),
+        }
+    }
+
+    #[test]
+    fn only_pixels_in_a_converged_neighbourhood_stop_sampling() {
+        let region = Region::new(2, 3, 8, 6);
+        let noisy_pixel = (6, 5);
+        let pixel_statistics = |x, y| {
+            if (x, y) == noisy_pixel {
+                statistics(16, (0.1, 2.))
+            } else {
+                statistics(16, (0.5, 0.5))
+            }
+        };
+        assert!(pixel_statistics(2, 3).relative_error() < 0.01);
+        assert!(pixel_statistics(noisy_pixel.0, noisy_pixel.1).relative_error() > 0.1);
+
+        let mut active_pixels = vec![true; region.pixel_count()];
+        let converged = update_active_pixels(pixel_statistics, region, 0.05, &mut active_pixels);
+
+        // The noisy pixel and the pixels around it keep going, but the rest of the flat region
+        // has converged
+        let near_noisy_pixel = Region::new(noisy_pixel.0 - 1, noisy_pixel.1 - 1, 3, 3);
+        for (index, &active) in active_pixels.iter().enumerate() {
+            let (x, y) = region.pixel(index);
+            assert_eq!(active, near_noisy_pixel.contains(x, y), "pixel ({x}, {y})");
+        }
+        assert_eq!(converged, region.pixel_count() - 9);
+    }
+
+    #[test]
+    fn pixels_with_too_few_samples_never_stop_sampling() {
+        let region = Region::full(4, 4);
+        let mut active_pixels = vec![true; region.pixel_count()];
+        let converged = update_active_pixels(
+            |_, _| statistics(1, (0.5, 0.5)),
+            region,
+            0.05,
+            &mut active_pixels,
+        );
+
+        assert_eq!(converged, 0);
+        assert!(active_pixels.iter().all(|&active| active));
+    }
+
+    #[test]
+    fn pixels_which_have_stopped_sampling_stay_stopped() {
+        let region = Region::full(4, 4);
+        let mut active_pixels = vec![false; region.pixel_count()];
+        let converged = update_active_pixels(
+            |_, _| statistics(16, (0.1, 2.)),
+            region,
+            0.05,
+            &mut active_pixels,
+        );
+
+        assert_eq!(converged, region.pixel_count());
+        assert!(active_pixels.iter().all(|&active| !active));
+    }
+}
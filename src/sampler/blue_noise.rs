@@ -0,0 +1,165 @@
+//! This module provides the [`BlueNoiseSampler`] type.
+
+use super::{sobol::shuffled_scrambled_sobol_4d, Sampler};
+use crate::random::{hash, seeded_rng};
+use rand::Rng;
+use std::sync::OnceLock;
+
+/// The width and height of the blue noise mask.
+const MASK_SIZE: usize = 64;
+
+/// A sampler which gives every pixel the same scrambled Sobol sequence, but shifted by a value
+/// from a blue noise mask in each dimension.
+///
+/// Neighbouring pixels get very different shifts, so the error in the image looks like fine
+/// high-frequency noise rather than blotches, especially at low sample counts. This is the
+/// method from "Blue-noise Dithered Sampling" by Georgiev and Fajardo.
+#[derive(Clone, Debug)]
+pub struct BlueNoiseSampler {
+    /// The global seed.
+    seed: u64,
+
+    /// The pixel that this sample is for.
+    pixel: (u32, u32),
+
+    /// Which sample of the pixel this is.
+    sample_index: u32,
+
+    /// The next dimension to use.
+    dimension: u32,
+}
+
+impl BlueNoiseSampler {
+    /// Create a sampler for the given sample of the given pixel.
+    pub fn new(seed: u64, pixel: (u32, u32), sample_index: u32) -> Self {
+        Self {
+            seed,
+            pixel,
+            sample_index,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn next_1d(&mut self) -> f64 {
+        let set_seed = hash(&[self.seed, (self.dimension / 4) as u64]) as u32;
+        let value =
+            shuffled_scrambled_sobol_4d(self.sample_index, set_seed)[(self.dimension % 4) as usize];
+
+        // Each dimension uses the mask tiled with a different offset, so that the dimensions
+        // aren't correlated with each other
+        let offset = hash(&[self.seed, self.dimension as u64, 1]);
+        let x = (self.pixel.0 as usize + offset as usize) % MASK_SIZE;
+        let y = (self.pixel.1 as usize + (offset >> 32) as usize) % MASK_SIZE;
+        let shift = blue_noise_mask()[y * MASK_SIZE + x];
+
+        self.dimension += 1;
+        (value + shift).fract()
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+}
+
+/// Get the blue noise mask, generating it the first time. The mask is a square of values in
+/// `[0, 1)` in row-major order, where every value appears once and similar values are spread
+/// far apart.
+fn blue_noise_mask() -> &'static [f64] {
+    static MASK: OnceLock<Vec<f64>> = OnceLock::new();
+    MASK.get_or_init(generate_blue_noise_mask)
+}
+
+/// Generate a blue noise mask with the void-and-cluster method from "The void-and-cluster method
+/// for dither array generation" by Robert Ulichney.
+fn generate_blue_noise_mask() -> Vec<f64> {
+    const PIXEL_COUNT: usize = MASK_SIZE * MASK_SIZE;
+    const SIGMA: f64 = 1.5;
+
+    // The energy that each pixel contributes to the pixels around it, wrapping around the edges
+    let kernel: Vec<f64> = (0..PIXEL_COUNT)
+        .map(|i| {
+            let wrapped = |d: usize| d.min(MASK_SIZE - d) as f64;
+            let (dx, dy) = (wrapped(i % MASK_SIZE), wrapped(i / MASK_SIZE));
+            (-(dx * dx + dy * dy) / (2. * SIGMA * SIGMA)).exp()
+        })
+        .collect();
+
+    let mut pattern = vec![false; PIXEL_COUNT];
+    let mut energy = vec![0.; PIXEL_COUNT];
+
+    let toggle = |pattern: &mut [bool], energy: &mut [f64], pixel: usize| {
+        pattern[pixel] = !pattern[pixel];
+        let sign = if pattern[pixel] { 1. } else { -1. };
+        let (px, py) = (pixel % MASK_SIZE, pixel / MASK_SIZE);
+
+        for (i, value) in energy.iter_mut().enumerate() {
+            let dx = (i % MASK_SIZE + MASK_SIZE - px) % MASK_SIZE;
+            let dy = (i / MASK_SIZE + MASK_SIZE - py) % MASK_SIZE;
+            *value += sign * kernel[dy * MASK_SIZE + dx];
+        }
+    };
+
+    // The tightest cluster is the set pixel with the most energy, and the largest void is the
+    // unset pixel with the least energy
+    let tightest_cluster = |pattern: &[bool], energy: &[f64]| -> usize {
+        (0..PIXEL_COUNT)
+            .filter(|&i| pattern[i])
+            .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .expect("There should be at least one set pixel")
+    };
+    let largest_void = |pattern: &[bool], energy: &[f64]| -> usize {
+        (0..PIXEL_COUNT)
+            .filter(|&i| !pattern[i])
+            .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .expect("There should be at least one unset pixel")
+    };
+
+    // Start with a random tenth of the pixels set
+    let mut rng = seeded_rng(0);
+    let initial_count = PIXEL_COUNT / 10;
+    let mut set_count = 0;
+    while set_count < initial_count {
+        let pixel = rng.gen_range(0..PIXEL_COUNT);
+        if !pattern[pixel] {
+            toggle(&mut pattern, &mut energy, pixel);
+            set_count += 1;
+        }
+    }
+
+    // Move pixels from the tightest clusters to the largest voids until the pattern is even
+    loop {
+        let cluster = tightest_cluster(&pattern, &energy);
+        toggle(&mut pattern, &mut energy, cluster);
+        let void = largest_void(&pattern, &energy);
+        toggle(&mut pattern, &mut energy, void);
+
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0; PIXEL_COUNT];
+
+    // Rank the initial pixels by removing the tightest clusters first
+    let (initial_pattern, initial_energy) = (pattern.clone(), energy.clone());
+    for rank in (0..initial_count).rev() {
+        let cluster = tightest_cluster(&pattern, &energy);
+        toggle(&mut pattern, &mut energy, cluster);
+        ranks[cluster] = rank;
+    }
+
+    // Rank the rest of the pixels by filling the largest voids first
+    let (mut pattern, mut energy) = (initial_pattern, initial_energy);
+    for rank in initial_count..PIXEL_COUNT {
+        let void = largest_void(&pattern, &energy);
+        toggle(&mut pattern, &mut energy, void);
+        ranks[void] = rank;
+    }
+
+    ranks
+        .into_iter()
+        .map(|rank| (rank as f64 + 0.5) / PIXEL_COUNT as f64)
+        .collect()
+}
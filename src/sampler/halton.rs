@@ -0,0 +1,96 @@
+//! This module provides the [`HaltonSampler`] type.
+
+use super::{sobol::nested_uniform_scramble, stratified::permute, Sampler};
+use crate::random::{hash, hash_to_unit_float};
+
+/// The first few prime numbers, which are the bases of the dimensions of the Halton sequence.
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+/// A sampler which uses the Halton sequence, where each dimension is the radical inverse of the
+/// sample index in a different prime base.
+///
+/// The digits of each dimension are Owen scrambled differently for every pixel, so that
+/// neighbouring pixels aren't correlated, and so that dimensions with large bases don't line up
+/// with each other. Dimensions beyond the first 64 just use random numbers.
+#[derive(Clone, Debug)]
+pub struct HaltonSampler {
+    /// The seed for this pixel.
+    pixel_seed: u64,
+
+    /// Which sample of the pixel this is.
+    sample_index: u32,
+
+    /// The next dimension to use.
+    dimension: u32,
+}
+
+impl HaltonSampler {
+    /// Create a sampler for the given sample of the given pixel.
+    pub fn new(seed: u64, (x, y): (u32, u32), sample_index: u32) -> Self {
+        Self {
+            pixel_seed: hash(&[seed, x as u64, y as u64]),
+            sample_index,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn next_1d(&mut self) -> f64 {
+        let dimension_hash = hash(&[self.pixel_seed, self.dimension as u64]);
+        let base = PRIMES.get(self.dimension as usize).copied();
+        self.dimension += 1;
+
+        match base {
+            Some(base) => scrambled_radical_inverse(self.sample_index, base, dimension_hash),
+            None => hash_to_unit_float(hash(&[dimension_hash, self.sample_index as u64])),
+        }
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+}
+
+/// Reflect the digits of the given number in the given base about the decimal point, randomly
+/// permuting each digit based on the digits before it.
+fn scrambled_radical_inverse(mut index: u32, base: u32, seed: u64) -> f64 {
+    // Reflecting the bits is just reversing them, and there's a much faster way to permute them
+    if base == 2 {
+        let bits = nested_uniform_scramble(index.reverse_bits(), seed as u32);
+        let tail = hash_to_unit_float(hash(&[seed, index as u64])) / (1u64 << 32) as f64;
+        return (bits as f64 / (1u64 << 32) as f64 + tail).min(1. - f64::EPSILON / 2.);
+    }
+
+    let inverse_base = 1. / base as f64;
+    let mut inverse_base_power = 1.;
+    let mut result = 0.;
+    let mut prefix = 0u64;
+
+    // The leading zeros of smaller indices get permuted into other digits too, so we have to go
+    // through as many digits as the largest index could have, otherwise the points wouldn't stay
+    // evenly spread out. Renders never have more than `u16::MAX` samples, so that's as far as we
+    // go unless this index is even bigger
+    for _ in 0..=index.max(u16::MAX as u32).ilog(base) {
+        let next = index / base;
+        let digit = index - next * base;
+        let permuted_digit = permute(digit, base, hash(&[seed, prefix]) as u32);
+
+        inverse_base_power *= inverse_base;
+        result += permuted_digit as f64 * inverse_base_power;
+        prefix = prefix
+            .wrapping_mul(base as u64)
+            .wrapping_add(digit as u64 + 1);
+        index = next;
+    }
+
+    // The rest of the digits would just be more permuted zeros, which are random, so we can add
+    // a random number for all of them at once
+    result += inverse_base_power * hash_to_unit_float(hash(&[seed, prefix]));
+    result.min(1. - f64::EPSILON / 2.)
+}
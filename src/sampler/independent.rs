@@ -0,0 +1,32 @@
+//! This module provides the [`IndependentSampler`] type.
+
+use super::Sampler;
+use crate::random::{sample_rng, RenderRng};
+use rand::Rng;
+
+/// A sampler which just uses independent uniform random numbers for every dimension.
+///
+/// This converges the slowest, but it's useful as a reference because it has no structure that
+/// could cause artifacts.
+#[derive(Clone, Debug)]
+pub struct IndependentSampler {
+    /// The random number generator for this sample.
+    rng: RenderRng,
+}
+
+impl IndependentSampler {
+    /// Create a sampler for the given sample of the given pixel.
+    pub fn new(seed: u64, (x, y): (u32, u32), sample_index: u32) -> Self {
+        Self {
+            rng: sample_rng(seed, x, y, sample_index),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn next_1d(&mut self) -> f64 {
+        self.rng.gen()
+    }
+
+    fn set_dimension(&mut self, _dimension: u32) {}
+}
@@ -0,0 +1,267 @@
+//! This module deals with samplers, which decide the random-looking numbers used for each sample
+//! of each pixel.
+//!
+//! Purely random numbers clump together and leave gaps, so they take a lot of samples to
+//! converge. Most of the samplers here spread the samples of each pixel out evenly instead, which
+//! gives a less noisy image with the same number of samples.
+//!
+//! Each sample is a point in a space with many dimensions, and every random decision made while
+//! tracing a path uses the next one or two dimensions. The samplers are careful to keep each
+//! dimension evenly spread out across all the samples of a pixel.
+
+mod blue_noise;
+mod halton;
+mod independent;
+mod sobol;
+mod stratified;
+
+use serde::{Deserialize, Serialize};
+
+pub use self::{
+    blue_noise::BlueNoiseSampler, halton::HaltonSampler, independent::IndependentSampler,
+    sobol::SobolSampler, stratified::StratifiedSampler,
+};
+
+/// A trait to represent a sampler for a single sample of a single pixel.
+pub trait Sampler {
+    /// Get the next dimension of the sample, as a number in `[0, 1)`.
+    fn next_1d(&mut self) -> f64;
+
+    /// Get the next two dimensions of the sample, each as a number in `[0, 1)`. Samplers which
+    /// spread out points in two dimensions together should override this.
+    fn next_2d(&mut self) -> (f64, f64) {
+        (self.next_1d(), self.next_1d())
+    }
+
+    /// Skip to the given dimension of the sample.
+    ///
+    /// Different paths use different numbers of dimensions at each bounce, so this lets the
+    /// tracer make sure that the same decision always uses the same dimension.
+    fn set_dimension(&mut self, dimension: u32);
+}
+
+/// The kinds of sampler that can be used for rendering.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Deserialize, Serialize)]
+pub enum SamplerKind {
+    /// Independent uniform random numbers. See [`IndependentSampler`].
+    Independent,
+
+    /// Jittered strata in each dimension. See [`StratifiedSampler`].
+    Stratified,
+
+    /// The Halton sequence. See [`HaltonSampler`].
+    Halton,
+
+    /// The Sobol sequence with Owen scrambling. See [`SobolSampler`].
+    #[default]
+    Sobol,
+
+    /// A low-discrepancy sequence offset by blue noise in screen space. See
+    /// [`BlueNoiseSampler`].
+    BlueNoise,
+}
+
+impl SamplerKind {
+    /// Create a sampler of this kind for the given sample of the given pixel.
+    ///
+    /// The sample count is how many samples some samplers spread out evenly, which is usually
+    /// how many samples each pixel gets. Any samples beyond that get spread out evenly again.
+    pub fn create(
+        self,
+        seed: u64,
+        pixel: (u32, u32),
+        sample_index: u32,
+        sample_count: u32,
+    ) -> AnySampler {
+        match self {
+            Self::Independent => {
+                AnySampler::Independent(IndependentSampler::new(seed, pixel, sample_index))
+            }
+            Self::Stratified => AnySampler::Stratified(StratifiedSampler::new(
+                seed,
+                pixel,
+                sample_index,
+                sample_count,
+            )),
+            Self::Halton => AnySampler::Halton(HaltonSampler::new(seed, pixel, sample_index)),
+            Self::Sobol => AnySampler::Sobol(SobolSampler::new(seed, pixel, sample_index)),
+            Self::BlueNoise => {
+                AnySampler::BlueNoise(BlueNoiseSampler::new(seed, pixel, sample_index))
+            }
+        }
+    }
+}
+
+/// A sampler of any kind, which is made by [`SamplerKind::create`].
+///
+/// A new sampler gets made for every sample of every pixel, so this is an enum rather than a
+/// boxed trait object, to avoid allocating each one.
+#[derive(Clone, Debug)]
+pub enum AnySampler {
+    /// See [`IndependentSampler`].
+    Independent(IndependentSampler),
+
+    /// See [`StratifiedSampler`].
+    Stratified(StratifiedSampler),
+
+    /// See [`HaltonSampler`].
+    Halton(HaltonSampler),
+
+    /// See [`SobolSampler`].
+    Sobol(SobolSampler),
+
+    /// See [`BlueNoiseSampler`].
+    BlueNoise(BlueNoiseSampler),
+}
+
+impl AnySampler {
+    /// The sampler inside.
+    fn inner(&mut self) -> &mut dyn Sampler {
+        match self {
+            Self::Independent(sampler) => sampler,
+            Self::Stratified(sampler) => sampler,
+            Self::Halton(sampler) => sampler,
+            Self::Sobol(sampler) => sampler,
+            Self::BlueNoise(sampler) => sampler,
+        }
+    }
+}
+
+impl Sampler for AnySampler {
+    fn next_1d(&mut self) -> f64 {
+        self.inner().next_1d()
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        self.inner().next_2d()
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.inner().set_dimension(dimension);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [SamplerKind; 5] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+        SamplerKind::BlueNoise,
+    ];
+
+    /// Take the first two dimensions of every sample of a pixel, out of the given sample count.
+    fn first_points(kind: SamplerKind, pixel: (u32, u32), sample_count: u32) -> Vec<(f64, f64)> {
+        (0..sample_count)
+            .map(|index| kind.create(7, pixel, index, sample_count).next_2d())
+            .collect()
+    }
+
+    /// Assert that every elementary interval of the given volume, which is every box with sides
+    /// `1 / 2^a` and `1 / 2^b` where `a + b = log2(points.len())` that's lined up with those
+    /// sides, has exactly one point in it.
+    fn assert_one_point_per_elementary_interval(points: &[(f64, f64)]) {
+        let count = points.len();
+        assert!(count.is_power_of_two());
+        let log_count = count.trailing_zeros();
+
+        for a in 0..=log_count {
+            let (columns, rows) = (1 << a, 1 << (log_count - a));
+            let mut cells = vec![0; count];
+            for &(x, y) in points {
+                let (column, row) = ((x * columns as f64) as usize, (y * rows as f64) as usize);
+                cells[row * columns + column] += 1;
+            }
+            assert!(
+                cells.iter().all(|&points| points == 1),
+                "Every {columns}x{rows} cell should have one point: {cells:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn every_sampler_gives_numbers_in_the_unit_interval() {
+        for kind in KINDS {
+            for pixel in [(0, 0), (3, 17), (1000, 63)] {
+                for index in 0..40 {
+                    let mut sampler = kind.create(3, pixel, index, 16);
+                    for dimension in (0..100).step_by(3) {
+                        sampler.set_dimension(dimension);
+                        let (x, y) = sampler.next_2d();
+                        let z = sampler.next_1d();
+                        for value in [x, y, z] {
+                            assert!(
+                                (0. ..1.).contains(&value),
+                                "{kind:?} gave {value} for sample {index} of {pixel:?}"
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sobol_points_are_in_every_elementary_interval() {
+        for log_count in 0..=8 {
+            for pixel in [(0, 0), (5, 9)] {
+                let points = first_points(SamplerKind::Sobol, pixel, 1 << log_count);
+                assert_one_point_per_elementary_interval(&points);
+            }
+        }
+    }
+
+    #[test]
+    fn halton_points_are_stratified_in_each_base() {
+        // The first dimension is in base 2 and the second is in base 3
+        for (base, dimension) in [(2usize, 0), (3, 1)] {
+            for power in 0..=5 {
+                let count = base.pow(power);
+                let mut strata = vec![0; count];
+                for point in first_points(SamplerKind::Halton, (2, 4), count as u32) {
+                    let value = if dimension == 0 { point.0 } else { point.1 };
+                    strata[(value * count as f64) as usize] += 1;
+                }
+                assert!(strata.iter().all(|&points| points == 1), "{strata:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn stratified_points_are_in_every_stratum() {
+        for count in [1, 4, 16, 64, 256] {
+            let columns = (count as f64).sqrt() as usize;
+            let mut cells = vec![0; count];
+            let mut strata = vec![0; count];
+
+            for index in 0..count as u32 {
+                let mut sampler = SamplerKind::Stratified.create(1, (3, 8), index, count as u32);
+                let (x, y) = sampler.next_2d();
+                let z = sampler.next_1d();
+                let (column, row) = ((x * columns as f64) as usize, (y * columns as f64) as usize);
+                cells[row * columns + column] += 1;
+                strata[(z * count as f64) as usize] += 1;
+            }
+
+            assert!(cells.iter().all(|&points| points == 1), "{cells:?}");
+            assert!(strata.iter().all(|&points| points == 1), "{strata:?}");
+        }
+    }
+
+    #[test]
+    fn stratified_samples_beyond_the_count_go_into_the_strata_again() {
+        let count = 16;
+        for round in 0..3 {
+            let mut strata = vec![0; count];
+            for index in round * count..(round + 1) * count {
+                let mut sampler =
+                    SamplerKind::Stratified.create(2, (1, 1), index as u32, count as u32);
+                strata[(sampler.next_1d() * count as f64) as usize] += 1;
+            }
+            assert!(strata.iter().all(|&points| points == 1), "{strata:?}");
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! This module provides the [`SobolSampler`] type.
+
+use super::Sampler;
+use crate::random::hash;
+
+/// The direction numbers for the first four dimensions of the Sobol sequence.
+const DIRECTIONS: [[u32; 32]; 4] = sobol_directions();
+
+/// A sampler which uses the Sobol sequence with Owen scrambling.
+///
+/// This uses the method from "Practical Hash-based Owen Scrambling" by Brent Burley. The sequence
+/// is generated four dimensions at a time, and each set of four dimensions gets its points
+/// shuffled and scrambled differently, so that the sets aren't correlated with each other. Every
+/// pixel gets a different scramble too.
+#[derive(Clone, Debug)]
+pub struct SobolSampler {
+    /// The seed for this pixel.
+    pixel_seed: u64,
+
+    /// Which sample of the pixel this is.
+    sample_index: u32,
+
+    /// The next dimension to use.
+    dimension: u32,
+
+    /// The most recently generated set of four dimensions, along with the index of the set.
+    cache: Option<(u32, [f64; 4])>,
+}
+
+impl SobolSampler {
+    /// Create a sampler for the given sample of the given pixel.
+    pub fn new(seed: u64, (x, y): (u32, u32), sample_index: u32) -> Self {
+        Self {
+            pixel_seed: hash(&[seed, x as u64, y as u64]),
+            sample_index,
+            dimension: 0,
+            cache: None,
+        }
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn next_1d(&mut self) -> f64 {
+        let set = self.dimension / 4;
+        let point = match self.cache {
+            Some((cached_set, point)) if cached_set == set => point,
+            _ => {
+                let seed = hash(&[self.pixel_seed, set as u64]) as u32;
+                let point = shuffled_scrambled_sobol_4d(self.sample_index, seed);
+                self.cache = Some((set, point));
+                point
+            }
+        };
+
+        let value = point[(self.dimension % 4) as usize];
+        self.dimension += 1;
+        value
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+}
+
+/// Get the point with the given index from a four-dimensional Sobol sequence, where the order of
+/// the points is shuffled and each dimension is Owen scrambled, using the given seed.
+pub(super) fn shuffled_scrambled_sobol_4d(index: u32, seed: u32) -> [f64; 4] {
+    let index = nested_uniform_scramble(index, seed);
+
+    let mut point = [0u32; 4];
+    for bit in 0..32 {
+        if index & (1 << bit) != 0 {
+            for (value, directions) in point.iter_mut().zip(&DIRECTIONS) {
+                *value ^= directions[bit];
+            }
+        }
+    }
+
+    let mut scrambled = [0.; 4];
+    for (dimension, (value, output)) in point.iter().zip(&mut scrambled).enumerate() {
+        let dimension_seed = hash(&[seed as u64, dimension as u64]) as u32;
+        *output = nested_uniform_scramble(*value, dimension_seed) as f64 / (1u64 << 32) as f64;
+    }
+    scrambled
+}
+
+/// Owen scramble the bits of a number, which randomly permutes it in a way that keeps the
+/// stratification of the Sobol sequence.
+pub(super) fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    laine_karras_permutation(value.reverse_bits(), seed).reverse_bits()
+}
+
+/// A hash where each bit only depends on the bits below it, so that reversing the bits before
+/// and after gives an Owen scramble.
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+/// Calculate the direction numbers for the first four dimensions of the Sobol sequence, using
+/// the primitive polynomials and initial numbers from Joe and Kuo.
+const fn sobol_directions() -> [[u32; 32]; 4] {
+    let mut directions = [[0; 32]; 4];
+
+    // The first dimension is just the van der Corput sequence
+    let mut i = 0;
+    while i < 32 {
+        directions[0][i] = 1 << (31 - i);
+        i += 1;
+    }
+
+    // The degree, coefficients, and initial direction numbers of each other dimension
+    let parameters: [(usize, u32, [u32; 3]); 3] =
+        [(1, 0, [1, 0, 0]), (2, 1, [1, 3, 0]), (3, 1, [1, 3, 1])];
+
+    let mut dimension = 1;
+    while dimension < 4 {
+        let (degree, coefficients, initial) = parameters[dimension - 1];
+        let mut m = [0u32; 32];
+
+        let mut k = 0;
+        while k < 32 {
+            m[k] = if k < degree {
+                initial[k]
+            } else {
+                let mut value = m[k - degree] ^ (m[k - degree] << degree);
+                let mut j = 1;
+                while j < degree {
+                    if (coefficients >> (degree - 1 - j)) & 1 == 1 {
+                        value ^= m[k - j] << j;
+                    }
+                    j += 1;
+                }
+                value
+            };
+
+            directions[dimension][k] = m[k] << (31 - k);
+            k += 1;
+        }
+
+        dimension += 1;
+    }
+
+    directions
+}
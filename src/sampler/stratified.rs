@@ -0,0 +1,129 @@
+//! This module provides the [`StratifiedSampler`] type.
+
+use super::Sampler;
+use crate::random::{hash, hash_to_unit_float};
+
+/// A sampler which splits each dimension into as many strata as there are samples, and puts one
+/// sample in a random place in each stratum. Pairs of dimensions are split into a grid of strata.
+///
+/// Each pixel and dimension shuffles its strata differently, so that the dimensions aren't
+/// correlated with each other. If a pixel gets more samples than the sample count, like with
+/// adaptive sampling, then every further round of that many samples goes into the strata again,
+/// shuffled differently.
+#[derive(Clone, Debug)]
+pub struct StratifiedSampler {
+    /// The seed for this pixel.
+    pixel_seed: u64,
+
+    /// Which sample of the pixel this is.
+    sample_index: u32,
+
+    /// How many samples get spread out across the strata in each round.
+    sample_count: u32,
+
+    /// The next dimension to use.
+    dimension: u32,
+}
+
+impl StratifiedSampler {
+    /// Create a sampler for the given sample of the given pixel, where each round of the given
+    /// number of samples gets spread out across the strata.
+    pub fn new(seed: u64, (x, y): (u32, u32), sample_index: u32, sample_count: u32) -> Self {
+        Self {
+            pixel_seed: hash(&[seed, x as u64, y as u64]),
+            sample_index,
+            sample_count: sample_count.max(1),
+            dimension: 0,
+        }
+    }
+
+    /// Pick a stratum out of the given number for this sample, and a random offset within it in
+    /// each of the given number of dimensions. The number of strata must be at least the sample
+    /// count.
+    fn stratum_and_jitter<const N: usize>(&mut self, strata: u32) -> (u32, [f64; N]) {
+        // There are always at least as many strata as samples in each round
+        let dimension_seed = hash(&[self.pixel_seed, self.dimension as u64]);
+        let round = self.sample_index / self.sample_count;
+        let stratum = permute(
+            self.sample_index % self.sample_count,
+            strata,
+            hash(&[dimension_seed, round as u64]) as u32,
+        );
+
+        let jitter = std::array::from_fn(|i| {
+            hash_to_unit_float(hash(&[dimension_seed, self.sample_index as u64, i as u64]))
+        });
+
+        self.dimension += N as u32;
+        (stratum, jitter)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn next_1d(&mut self) -> f64 {
+        let strata = self.sample_count;
+        let (stratum, [jitter]) = self.stratum_and_jitter(strata);
+        (stratum as f64 + jitter) / strata as f64
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        // If the sample count isn't square, then the grid has a few more cells than samples, and
+        // we just leave some random cells empty
+        let columns = (self.sample_count as f64).sqrt().ceil() as u32;
+        let rows = self.sample_count.div_ceil(columns);
+
+        let (stratum, [jitter_x, jitter_y]) = self.stratum_and_jitter(columns * rows);
+        (
+            ((stratum % columns) as f64 + jitter_x) / columns as f64,
+            ((stratum / columns) as f64 + jitter_y) / rows as f64,
+        )
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+}
+
+/// Map an index in `[0, length)` to a different index in the same range, such that every seed
+/// gives a different random permutation.
+///
+/// This is from "Correlated Multi-Jittered Sampling" by Andrew Kensler.
+pub(super) fn permute(index: u32, length: u32, seed: u32) -> u32 {
+    debug_assert!(index < length, "The index must be less than the length");
+
+    let mut mask = length - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    // This is a bijection on `[0, mask]`, so we keep applying it until we land back in range
+    let mut i = index;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & mask) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & mask) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & mask) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= mask;
+        i ^= i >> 5;
+
+        if i < length {
+            break;
+        }
+    }
+
+    ((i as u64 + seed as u64) % length as u64) as u32
+}
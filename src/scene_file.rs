@@ -0,0 +1,650 @@
+//! This module handles loading scenes from TOML files.
+//!
+//! A scene file describes the camera, a set of named materials, and a list of objects which refer
+//! to those materials by name. Vectors and colours are written as arrays of three numbers. For
+//! example:
+//!
+//! ```toml
+//! [camera]
+//! look_from = [13, 2, 3]
+//! look_at = [0, 0, 0]
+//! vertical_fov_degrees = 20
+//!
+//! [materials.ground]
+//! type = "lambertian"
+//! colour = [0.5, 0.5, 0.5]
+//!
+//! [materials.glass]
+//! type = "dielectric"
+//! colour = [1, 1, 1]
+//! refraction_ratio = 1.5
+//!
+//! [[objects]]
+//! type = "sphere"
+//! centre = [0, -1000, 0]
+//! radius = 1000
+//! material = "ground"
+//!
+//! [[objects]]
+//! type = "sphere"
+//! centre = [0, 1, 0]
+//! radius = 1
+//! material = "glass"
+//!
+//! [[objects]]
+//! type = "mesh"
+//! path = "teapot.obj"
+//! scale = 0.5
+//! offset = [-4, 0, 0]
+//! ```
+//!
+//! Meshes use the materials from their MTL file unless a material is given, and their paths are
+//! relative to the scene file.
+//!
+//! Anywhere that a material takes a colour, it can also take a texture instead:
+//!
+//! ```toml
+//! [materials.chequered]
+//! type = "lambertian"
+//! colour = { type = "checker", even = [0.2, 0.3, 0.1], odd = [0.9, 0.9, 0.9], scale = 0.5 }
+//!
+//! [materials.marble]
+//! type = "lambertian"
+//! colour = { type = "noise", scale = 4 }
+//!
+//! [materials.earth]
+//! type = "lambertian"
+//! colour = { type = "image", path = "earthmap.jpg" }
+//! ```
+//!
+//! The environment lights the scene from infinitely far away. It defaults to a blue sky, but it
+//! can also be black, a plain colour, a custom gradient, or an equirectangular image:
+//!
+//! ```toml
+//! [environment]
+//! type = "map"
+//! path = "studio.exr"
+//! rotation_degrees = 90
+//! intensity = 2
+//! ```
+
+use crate::{
+    camera::CameraOpts,
+    environment::{EnvironmentMap, Gradient, SharedEnvironment},
+    material::{Dielectric, DiffuseLight, Lambertian, Metal, SharedMaterial},
+    object::{Mesh, MeshOpts, Object, Scene, Sphere, Triangle},
+    texture::{Checker, CheckerMapping, ImageTexture, Noise, SharedTexture},
+    vector::{v, Colour, Point, Vec3},
+};
+use color_eyre::{
+    eyre::{eyre, Context},
+    Result,
+};
+use core::f64::consts::PI;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+/// The contents of a scene file.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneFile {
+    /// The camera used to view the scene.
+    #[serde(default)]
+    pub camera: CameraDescription,
+
+    /// All the materials in the scene, indexed by name.
+    #[serde(default)]
+    pub materials: HashMap<String, MaterialDescription>,
+
+    /// All the objects in the scene.
+    #[serde(default)]
+    pub objects: Vec<ObjectDescription>,
+
+    /// The environment surrounding the scene.
+    #[serde(default)]
+    pub environment: EnvironmentDescription,
+
+    /// The directory containing the scene file, which other paths are relative to.
+    #[serde(skip)]
+    pub directory: PathBuf,
+}
+
+/// The description of a camera. This contains all the fields of [`CameraOpts`] except for the
+/// width and height, which are decided when rendering.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraDescription {
+    /// The vertical field of view, in degrees.
+    pub vertical_fov_degrees: f64,
+
+    /// The point where the camera is looking from.
+    pub look_from: Point,
+
+    /// The point that the camera is looking at.
+    pub look_at: Point,
+
+    /// A vector that decides which way is up for the camera.
+    pub view_up: Vec3,
+
+    /// The width of the aperture.
+    pub aperture_width: f64,
+
+    /// The distance between the camera lens and the focus plane.
+    pub focus_distance: f64,
+}
+
+impl Default for CameraDescription {
+    fn default() -> Self {
+        Self {
+            vertical_fov_degrees: 20.,
+            look_from: v!(13, 2, 3),
+            look_at: v!(0, 0, 0),
+            view_up: v!(0, 1, 0),
+            aperture_width: 0.1,
+            focus_distance: 10.,
+        }
+    }
+}
+
+impl CameraDescription {
+    /// Turn this description into a full set of camera options by giving it a width and height.
+    pub fn to_camera_opts(&self, width: u32, height: u32) -> CameraOpts {
+        CameraOpts {
+            width,
+            height,
+            vertical_fov_degrees: self.vertical_fov_degrees,
+            look_from: self.look_from,
+            look_at: self.look_at,
+            view_up: self.view_up,
+            aperture_width: self.aperture_width,
+            focus_distance: self.focus_distance,
+        }
+    }
+
+    /// The distance from the camera to the point that it's looking at.
+    pub fn distance(&self) -> f64 {
+        (self.look_at - self.look_from).len()
+    }
+
+    /// The unit vectors pointing forwards, right, and up from the camera's point of view. These
+    /// match the basis used by [`Camera`](crate::camera::Camera).
+    pub fn basis(&self) -> (Vec3, Vec3, Vec3) {
+        let forward = (self.look_at - self.look_from).normalise();
+        let right = forward.cross(self.view_up).normalise();
+        let up = right.cross(forward).normalise();
+        (forward, right, up)
+    }
+
+    /// Swing the camera around the point that it's looking at, by the given angles in degrees.
+    /// A positive yaw swings it to its right around the up vector, and a positive pitch swings it
+    /// upwards, stopping just short of looking straight along the up vector.
+    pub fn orbit(&mut self, yaw_degrees: f64, pitch_degrees: f64) {
+        let axis = self.view_up.normalise();
+        let offset = rotate(
+            self.look_from - self.look_at,
+            axis,
+            yaw_degrees.to_radians(),
+        );
+
+        // The angle between the up vector and the offset, which must stay between 0 and 180
+        // degrees, and which gets smaller as the camera swings upwards
+        let elevation = offset.normalise().dot(axis).clamp(-1., 1.).acos();
+        let pitch = pitch_degrees
+            .to_radians()
+            .clamp(elevation + MIN_ELEVATION - PI, elevation - MIN_ELEVATION);
+
+        // Rotating anticlockwise around the right vector swings the camera downwards
+        let right = (-offset).cross(self.view_up).normalise();
+        self.look_from = self.look_at + rotate(offset, right, -pitch);
+    }
+
+    /// Slide the camera and the point that it's looking at sideways and up, by the given
+    /// proportions of the height of the view at the point that it's looking at. This means that
+    /// dragging the image by some proportion of its height moves the scene by the same amount.
+    pub fn pan(&mut self, right_amount: f64, up_amount: f64) {
+        let (_, right, up) = self.basis();
+        let view_height =
+            2. * self.distance() * (self.vertical_fov_degrees.to_radians() / 2.).tan();
+        let offset = (right * right_amount + up * up_amount) * view_height;
+
+        self.look_from += offset;
+        self.look_at += offset;
+    }
+
+    /// Move the camera towards the point that it's looking at, so that the distance between them
+    /// gets multiplied by the given factor. The focus distance gets scaled too, so whatever was
+    /// in focus stays in focus.
+    pub fn zoom(&mut self, factor: f64) {
+        let factor = factor.max(MIN_DISTANCE / self.distance());
+        self.look_from = self.look_at + (self.look_from - self.look_at) * factor;
+        self.focus_distance *= factor;
+    }
+
+    /// Move the camera and the point that it's looking at by the given distances forwards, right,
+    /// and up from the camera's point of view.
+    pub fn fly(&mut self, forward_amount: f64, right_amount: f64, up_amount: f64) {
+        let (forward, right, up) = self.basis();
+        let offset = forward * forward_amount + right * right_amount + up * up_amount;
+
+        self.look_from += offset;
+        self.look_at += offset;
+    }
+
+    /// The command line arguments which give this camera.
+    pub fn to_arguments(&self) -> Vec<String> {
+        vec![
+            format!("--look-from={}", self.look_from),
+            format!("--look-at={}", self.look_at),
+            format!("--view-up={}", self.view_up),
+            format!("--fov={}", self.vertical_fov_degrees),
+            format!("--aperture={}", self.aperture_width),
+            format!("--focus-distance={}", self.focus_distance),
+        ]
+    }
+
+    /// The `[camera]` table of a scene file which gives this camera.
+    pub fn to_toml(&self) -> String {
+        #[derive(Serialize)]
+        struct Table<'a> {
+            camera: &'a CameraDescription,
+        }
+
+        toml::to_string(&Table { camera: self })
+            .expect("A camera description should always serialize to TOML")
+    }
+}
+
+/// The smallest angle in radians that the camera can get to its up vector when orbiting, so that
+/// it never flips over the top.
+const MIN_ELEVATION: f64 = 0.01;
+
+/// The closest that zooming can bring the camera to the point that it's looking at.
+const MIN_DISTANCE: f64 = 1e-3;
+
+/// Rotate the given vector anticlockwise around the given unit axis by the given angle in
+/// radians, using Rodrigues' rotation formula.
+fn rotate(vector: Vec3, axis: Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    vector * cos + axis.cross(vector) * sin + axis * axis.dot(vector) * (1. - cos)
+}
+
+/// The description of a material.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum MaterialDescription {
+    /// See [`Lambertian`].
+    Lambertian {
+        /// The colour of the material.
+        colour: TextureDescription,
+    },
+
+    /// See [`Metal`].
+    Metal {
+        /// The colour of the material.
+        colour: TextureDescription,
+
+        /// The fuzziness of the material, in `[0, 1]`.
+        #[serde(default)]
+        fuzz: f64,
+    },
+
+    /// See [`Dielectric`].
+    Dielectric {
+        /// The colour tint of the material.
+        #[serde(default = "white")]
+        colour: TextureDescription,
+
+        /// The ratio of the refractive index of the external material to the refractive index of
+        /// the internal material.
+        refraction_ratio: f64,
+    },
+
+    /// See [`DiffuseLight`].
+    DiffuseLight {
+        /// The colour of the emitted light. This can be brighter than 1 in each channel.
+        colour: Colour,
+    },
+}
+
+/// The description of a texture, which is either a plain colour or a table describing a more
+/// complicated texture.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum TextureDescription {
+    /// A plain colour.
+    Solid(Colour),
+
+    /// A more complicated texture.
+    Texture(TextureKind),
+}
+
+/// The description of a texture which isn't just a plain colour.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TextureKind {
+    /// See [`Checker`].
+    Checker {
+        /// The texture of the even squares.
+        even: Box<TextureDescription>,
+
+        /// The texture of the odd squares.
+        odd: Box<TextureDescription>,
+
+        /// The size of each square.
+        scale: f64,
+
+        /// Should the squares be drawn in UV space rather than in 3D space?
+        #[serde(default)]
+        uv: bool,
+    },
+
+    /// See [`Noise`].
+    Noise {
+        /// The colour of the brightest parts of the texture.
+        #[serde(default = "white_colour")]
+        colour: Colour,
+
+        /// How many stripes to fit in each unit of space.
+        #[serde(default = "one")]
+        scale: f64,
+
+        /// How many octaves of turbulence to use to distort the stripes.
+        #[serde(default = "seven")]
+        octaves: u32,
+
+        /// The seed for the random noise.
+        #[serde(default)]
+        seed: u64,
+    },
+
+    /// See [`ImageTexture`].
+    Image {
+        /// The path to the image, relative to the scene file.
+        path: PathBuf,
+    },
+}
+
+impl TextureDescription {
+    /// Build the texture that this description describes. Paths are relative to the given
+    /// directory.
+    pub fn build(&self, directory: &Path) -> Result<SharedTexture> {
+        Ok(match self {
+            Self::Solid(colour) => Arc::new(*colour),
+            Self::Texture(TextureKind::Checker {
+                even,
+                odd,
+                scale,
+                uv,
+            }) => Arc::new(Checker::new(
+                even.build(directory)?,
+                odd.build(directory)?,
+                *scale,
+                if *uv {
+                    CheckerMapping::Uv
+                } else {
+                    CheckerMapping::Spatial
+                },
+            )),
+            Self::Texture(TextureKind::Noise {
+                colour,
+                scale,
+                octaves,
+                seed,
+            }) => Arc::new(Noise::new(*colour, *scale, *octaves, *seed)),
+            Self::Texture(TextureKind::Image { path }) => {
+                Arc::new(ImageTexture::load(directory.join(path))?)
+            }
+        })
+    }
+}
+
+/// A white texture, for use as a serde default.
+fn white() -> TextureDescription {
+    TextureDescription::Solid(v!(1))
+}
+
+/// The colour white, for use as a serde default.
+fn white_colour() -> Colour {
+    v!(1)
+}
+
+/// The number 7, for use as a serde default.
+fn seven() -> u32 {
+    7
+}
+
+/// The number 1, for use as a serde default.
+fn one() -> f64 {
+    1.
+}
+
+/// The zero vector, for use as a serde default.
+fn zero_vector() -> Vec3 {
+    Vec3::ZERO
+}
+
+impl MaterialDescription {
+    /// Build the material that this description describes. Paths are relative to the given
+    /// directory.
+    pub fn build(&self, directory: &Path) -> Result<SharedMaterial> {
+        Ok(match self {
+            Self::Lambertian { colour } => Arc::new(Lambertian::new(colour.build(directory)?)),
+            Self::Metal { colour, fuzz } => Arc::new(Metal::new(colour.build(directory)?, *fuzz)),
+            Self::Dielectric {
+                colour,
+                refraction_ratio,
+            } => Arc::new(Dielectric::new(colour.build(directory)?, *refraction_ratio)),
+            Self::DiffuseLight { colour } => Arc::new(DiffuseLight::new(*colour)),
+        })
+    }
+}
+
+/// The description of an object.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ObjectDescription {
+    /// See [`Sphere`].
+    Sphere {
+        /// The centre of the sphere.
+        centre: Point,
+
+        /// The radius of the sphere.
+        radius: f64,
+
+        /// The name of the material of the sphere.
+        material: String,
+    },
+
+    /// See [`Triangle`].
+    Triangle {
+        /// The three vertices of the triangle. The front face is the one where these are
+        /// anticlockwise.
+        vertices: [Point; 3],
+
+        /// The name of the material of the triangle.
+        material: String,
+    },
+
+    /// See [`Mesh`].
+    Mesh {
+        /// The path to the OBJ file, relative to the scene file.
+        path: PathBuf,
+
+        /// The name of a material to use for the whole mesh, instead of the materials from the
+        /// MTL file.
+        #[serde(default)]
+        material: Option<String>,
+
+        /// How much to scale the mesh by.
+        #[serde(default = "one")]
+        scale: f64,
+
+        /// How far to move the mesh, after scaling it.
+        #[serde(default = "zero_vector")]
+        offset: Vec3,
+    },
+}
+
+/// The description of an environment.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum EnvironmentDescription {
+    /// No light comes from the environment.
+    Black,
+
+    /// The same colour in every direction.
+    Solid {
+        /// The colour of the environment. This can be brighter than 1 in each channel.
+        colour: Colour,
+    },
+
+    /// See [`Gradient`].
+    Gradient {
+        /// The colour looking straight down.
+        bottom: Colour,
+
+        /// The colour looking straight up.
+        top: Colour,
+    },
+
+    /// See [`EnvironmentMap`].
+    Map {
+        /// The path to the equirectangular image, relative to the scene file.
+        path: PathBuf,
+
+        /// How far to rotate the image anticlockwise around the Y axis, in degrees.
+        #[serde(default)]
+        rotation_degrees: f64,
+
+        /// How much to scale the brightness of the image by.
+        #[serde(default = "one")]
+        intensity: f64,
+    },
+}
+
+/// The default environment is a simple blue sky.
+impl Default for EnvironmentDescription {
+    fn default() -> Self {
+        let Gradient { bottom, top } = Gradient::default();
+        Self::Gradient { bottom, top }
+    }
+}
+
+impl EnvironmentDescription {
+    /// Build the environment that this description describes. Paths are relative to the given
+    /// directory.
+    pub fn build(&self, directory: &Path) -> Result<SharedEnvironment> {
+        Ok(match self {
+            Self::Black => Arc::new(Colour::ZERO),
+            Self::Solid { colour } => Arc::new(*colour),
+            Self::Gradient { bottom, top } => Arc::new(Gradient::new(*bottom, *top)),
+            Self::Map {
+                path,
+                rotation_degrees,
+                intensity,
+            } => Arc::new(EnvironmentMap::load(
+                directory.join(path),
+                rotation_degrees.to_radians(),
+                *intensity,
+            )?),
+        })
+    }
+}
+
+/// Parse an environment from a short description, as given on the command line.
+///
+/// This is either `black`, `sky` for the default sky, a colour written as three comma-separated
+/// numbers, or the path to an equirectangular image.
+impl FromStr for EnvironmentDescription {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let components: Option<Vec<f64>> = s
+            .split(',')
+            .map(|component| component.trim().parse().ok())
+            .collect();
+
+        Ok(match (s, components.as_deref()) {
+            ("black", _) => Self::Black,
+            ("sky", _) => Self::default(),
+            (_, Some(&[r, g, b])) => Self::Solid {
+                colour: v!(r, g, b),
+            },
+            _ => Self::Map {
+                path: PathBuf::from(s),
+                rotation_degrees: 0.,
+                intensity: 1.,
+            },
+        })
+    }
+}
+
+impl SceneFile {
+    /// Read and parse a scene file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .wrap_err_with(|| format!("When trying to read scene file {}", path.display()))?;
+
+        let mut scene_file: Self = toml::from_str(&text)
+            .wrap_err_with(|| format!("When trying to parse scene file {}", path.display()))?;
+        scene_file.directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(scene_file)
+    }
+
+    /// Build all the objects in this scene.
+    pub fn build_scene(&self) -> Result<Scene> {
+        let materials: HashMap<&str, SharedMaterial> = self
+            .materials
+            .iter()
+            .map(|(name, material)| {
+                let material = material
+                    .build(&self.directory)
+                    .wrap_err_with(|| format!("When trying to build material {name:?}"))?;
+                Ok((name.as_str(), material))
+            })
+            .collect::<Result<_>>()?;
+
+        let get_material = |name: &str| -> Result<SharedMaterial> {
+            materials
+                .get(name)
+                .cloned()
+                .ok_or_else(|| eyre!("Unknown material {name:?}"))
+        };
+
+        self.objects
+            .iter()
+            .map(|object| -> Result<Box<dyn Object + Sync + Send>> {
+                Ok(match object {
+                    ObjectDescription::Sphere {
+                        centre,
+                        radius,
+                        material,
+                    } => Box::new(Sphere::new(*centre, *radius, get_material(material)?)),
+                    ObjectDescription::Triangle { vertices, material } => {
+                        Box::new(Triangle::new(*vertices, get_material(material)?))
+                    }
+                    ObjectDescription::Mesh {
+                        path,
+                        material,
+                        scale,
+                        offset,
+                    } => Box::new(Mesh::load_obj(
+                        self.directory.join(path),
+                        MeshOpts {
+                            material: material.as_deref().map(get_material).transpose()?,
+                            scale: *scale,
+                            offset: *offset,
+                        },
+                    )?),
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,61 @@
+//! This module provides the [`Checker`] texture.
+
+use super::Texture;
+use crate::vector::{Colour, Point};
+
+/// A checkerboard pattern alternating between two other textures.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Checker<E: Texture, O: Texture> {
+    /// The texture of the even squares.
+    pub even: E,
+
+    /// The texture of the odd squares.
+    pub odd: O,
+
+    /// The size of each square. For [`CheckerMapping::Uv`], this is in UV space, so a scale of
+    /// 0.1 gives 10 squares in each direction.
+    pub scale: f64,
+
+    /// How the squares get mapped onto the surface.
+    pub mapping: CheckerMapping,
+}
+
+/// How the squares of a [`Checker`] texture are mapped onto a surface.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CheckerMapping {
+    /// The checkerboard is a solid 3D pattern of cubes, so it looks the same regardless of the
+    /// shape of the object.
+    #[default]
+    Spatial,
+
+    /// The checkerboard is drawn in the UV coordinates of the surface.
+    Uv,
+}
+
+impl<E: Texture, O: Texture> Checker<E, O> {
+    pub fn new(even: E, odd: O, scale: f64, mapping: CheckerMapping) -> Self {
+        Self {
+            even,
+            odd,
+            scale,
+            mapping,
+        }
+    }
+}
+
+impl<E: Texture, O: Texture> Texture for Checker<E, O> {
+    fn value(&self, uv: (f64, f64), point: Point) -> Colour {
+        let square = |x: f64| (x / self.scale).floor() as i64;
+
+        let sum = match self.mapping {
+            CheckerMapping::Spatial => square(point.x) + square(point.y) + square(point.z),
+            CheckerMapping::Uv => square(uv.0) + square(uv.1),
+        };
+
+        if sum.rem_euclid(2) == 0 {
+            self.even.value(uv, point)
+        } else {
+            self.odd.value(uv, point)
+        }
+    }
+}
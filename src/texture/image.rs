@@ -0,0 +1,70 @@
+//! This module provides the [`ImageTexture`] type.
+
+use super::Texture;
+use crate::{
+    tonemap::srgb_decode,
+    vector::{Colour, Point},
+};
+use color_eyre::{eyre::Context, Result};
+use image::{DynamicImage, Rgb32FImage};
+use std::path::Path;
+
+/// A texture which wraps an image around a surface using its UV coordinates.
+///
+/// The image repeats outside of `[0, 1]` in each direction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageTexture {
+    /// The image, in linear colour space.
+    image: Rgb32FImage,
+}
+
+impl ImageTexture {
+    /// Create a new texture from an image which is already in linear colour space.
+    pub fn new(image: Rgb32FImage) -> Self {
+        Self { image }
+    }
+
+    /// Load an image texture from a file.
+    ///
+    /// Float images like EXR and HDR files are assumed to be linear already, and every other
+    /// image is assumed to be sRGB encoded.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let image = image::open(path)
+            .wrap_err_with(|| format!("When trying to load texture {}", path.display()))?;
+
+        let is_float = matches!(
+            image,
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+        );
+        let mut image = image.into_rgb32f();
+
+        if !is_float {
+            for channel in image.iter_mut() {
+                *channel = srgb_decode(*channel as f64) as f32;
+            }
+        }
+
+        Ok(Self { image })
+    }
+
+    /// Get the underlying image, in linear colour space.
+    pub fn into_image(self) -> Rgb32FImage {
+        self.image
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, (u, v): (f64, f64), _point: Point) -> Colour {
+        let (width, height) = self.image.dimensions();
+        if width == 0 || height == 0 {
+            return Colour::ZERO;
+        }
+
+        // V goes up the image, but the rows of the image go down
+        let x = (u.rem_euclid(1.) * width as f64) as u32;
+        let y = ((1. - v.rem_euclid(1.)) * height as f64) as u32;
+
+        Colour::from(*self.image.get_pixel(x.min(width - 1), y.min(height - 1)))
+    }
+}
@@ -0,0 +1,47 @@
+//! This module deals with textures, which let the colour of a material vary over a surface.
+
+mod checker;
+mod image;
+mod noise;
+
+use crate::vector::{Colour, Point};
+use std::sync::Arc;
+
+pub use self::{
+    checker::{Checker, CheckerMapping},
+    image::ImageTexture,
+    noise::{Noise, Perlin},
+};
+
+/// A trait to represent a texture.
+///
+/// Textures must be [`Send`] and [`Sync`] so that materials can be shared between threads.
+pub trait Texture: Send + Sync {
+    /// Get the colour of the texture at the given UV coordinates and point in space.
+    ///
+    /// The UV coordinates should both be in `[0, 1]`, but textures should cope with values outside
+    /// that range.
+    fn value(&self, uv: (f64, f64), point: Point) -> Colour;
+}
+
+/// A plain colour is a texture which is the same everywhere.
+impl Texture for Colour {
+    #[inline]
+    fn value(&self, _uv: (f64, f64), _point: Point) -> Colour {
+        *self
+    }
+}
+
+// This lets materials share textures, and lets us choose textures at runtime with
+// `Arc<dyn Texture>`.
+impl<T> Texture for Arc<T>
+where
+    T: Texture + ?Sized,
+{
+    fn value(&self, uv: (f64, f64), point: Point) -> Colour {
+        (**self).value(uv, point)
+    }
+}
+
+/// A texture which can be shared between several materials and chosen at runtime.
+pub type SharedTexture = Arc<dyn Texture + Sync + Send>;
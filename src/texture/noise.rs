@@ -0,0 +1,124 @@
+//! This module provides the [`Noise`] texture and the [`Perlin`] noise generator.
+
+use super::Texture;
+use crate::{
+    random::seeded_rng,
+    vector::{Colour, Point, Vec3},
+};
+use rand::seq::SliceRandom;
+
+/// How many random vectors a [`Perlin`] generator uses. This must be a power of 2.
+const POINT_COUNT: usize = 256;
+
+/// A generator for Perlin noise.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Perlin {
+    /// Random unit vectors at the lattice points.
+    random_vectors: Box<[Vec3; POINT_COUNT]>,
+
+    /// Permutations of the lattice indices in each axis.
+    permutations: Box<[[usize; POINT_COUNT]; 3]>,
+}
+
+impl Perlin {
+    /// Create a new random Perlin noise generator. The same seed always gives the same noise.
+    pub fn new(seed: u64) -> Self {
+        let mut rng = seeded_rng(seed);
+
+        let random_vectors =
+            Box::new([(); POINT_COUNT].map(|_| Vec3::random_unit_vector(&mut rng)));
+        let permutations = Box::new([(); 3].map(|_| {
+            let mut permutation: [usize; POINT_COUNT] = std::array::from_fn(|i| i);
+            permutation.shuffle(&mut rng);
+            permutation
+        }));
+
+        Self {
+            random_vectors,
+            permutations,
+        }
+    }
+
+    /// Get the noise value at the given point. This is in `[-1, 1]`.
+    pub fn noise(&self, point: Point) -> f64 {
+        let floor = point.map(f64::floor);
+        let fraction = point - floor;
+
+        // Hermite smoothing to avoid grid artifacts
+        let smooth = fraction.map(|t| t * t * (3. - 2. * t));
+
+        let index = |axis: usize, offset: usize| {
+            let lattice = floor.axis(axis) as i64 + offset as i64;
+            self.permutations[axis][(lattice & (POINT_COUNT as i64 - 1)) as usize]
+        };
+
+        let mut accumulator = 0.;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = self.random_vectors[index(0, di) ^ index(1, dj) ^ index(2, dk)];
+
+                    let (i, j, k) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3::new(fraction.x - i, fraction.y - j, fraction.z - k);
+
+                    accumulator += (i * smooth.x + (1. - i) * (1. - smooth.x))
+                        * (j * smooth.y + (1. - j) * (1. - smooth.y))
+                        * (k * smooth.z + (1. - k) * (1. - smooth.z))
+                        * gradient.dot(weight);
+                }
+            }
+        }
+
+        accumulator
+    }
+
+    /// Get the turbulence at the given point, which is the sum of several octaves of noise. This
+    /// is in `[0, 2)`.
+    pub fn turbulence(&self, point: Point, octaves: u32) -> f64 {
+        let mut accumulator = 0.;
+        let mut point = point;
+        let mut weight = 1.;
+
+        for _ in 0..octaves {
+            accumulator += weight * self.noise(point);
+            weight *= 0.5;
+            point *= 2.;
+        }
+
+        accumulator.abs()
+    }
+}
+
+/// A marble-like texture made from Perlin noise.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Noise {
+    /// The noise generator.
+    pub perlin: Perlin,
+
+    /// The colour of the brightest parts of the texture.
+    pub colour: Colour,
+
+    /// How many stripes to fit in each unit of space.
+    pub scale: f64,
+
+    /// How many octaves of turbulence to use to distort the stripes.
+    pub octaves: u32,
+}
+
+impl Noise {
+    pub fn new(colour: Colour, scale: f64, octaves: u32, seed: u64) -> Self {
+        Self {
+            perlin: Perlin::new(seed),
+            colour,
+            scale,
+            octaves,
+        }
+    }
+}
+
+impl Texture for Noise {
+    fn value(&self, _uv: (f64, f64), point: Point) -> Colour {
+        let phase = self.scale * point.z + 10. * self.perlin.turbulence(point, self.octaves);
+        self.colour * 0.5 * (1. + phase.sin())
+    }
+}
@@ -0,0 +1,356 @@
+//! This module provides the [`Region`] type, and ways to split a region into tiles.
+
+use color_eyre::{
+    eyre::{ensure, eyre, Context},
+    Report, Result,
+};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// A rectangle of pixels in an image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Region {
+    /// The X coordinate of the left column of the region.
+    pub x: u32,
+
+    /// The Y coordinate of the top row of the region.
+    pub y: u32,
+
+    /// The width of the region.
+    pub width: u32,
+
+    /// The height of the region.
+    pub height: u32,
+}
+
+impl Region {
+    /// Create a new region with the given top left corner and size.
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Create a region which covers a whole image of the given size.
+    pub fn full(width: u32, height: u32) -> Self {
+        Self::new(0, 0, width, height)
+    }
+
+    /// The X coordinate just past the right column of the region.
+    pub fn right(&self) -> u32 {
+        self.x + self.width
+    }
+
+    /// The Y coordinate just past the bottom row of the region.
+    pub fn bottom(&self) -> u32 {
+        self.y + self.height
+    }
+
+    /// How many pixels are in the region.
+    pub fn pixel_count(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+
+    /// Is the given pixel in this region?
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        (self.x..self.right()).contains(&x) && (self.y..self.bottom()).contains(&y)
+    }
+
+    /// Get the pixel at the given index in this region, where the pixels are in row-major order.
+    pub fn pixel(&self, index: usize) -> (u32, u32) {
+        (
+            self.x + (index % self.width as usize) as u32,
+            self.y + (index / self.width as usize) as u32,
+        )
+    }
+
+    /// Get the index of the given pixel in this region, where the pixels are in row-major order.
+    /// The pixel must be in the region.
+    pub fn index(&self, x: u32, y: u32) -> usize {
+        debug_assert!(self.contains(x, y), "The pixel must be in the region");
+        (y - self.y) as usize * self.width as usize + (x - self.x) as usize
+    }
+
+    /// Get the part of this region which is also in the other one, if there is any.
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let (x, y) = (self.x.max(other.x), self.y.max(other.y));
+        let (right, bottom) = (
+            self.right().min(other.right()),
+            self.bottom().min(other.bottom()),
+        );
+
+        (x < right && y < bottom).then(|| Self::new(x, y, right - x, bottom - y))
+    }
+
+    /// Grow this region by the given number of pixels on every side, without going past the
+    /// edges of the given bounds.
+    pub fn expand(&self, by: u32, bounds: &Self) -> Self {
+        let (x, y) = (
+            self.x.saturating_sub(by).max(bounds.x),
+            self.y.saturating_sub(by).max(bounds.y),
+        );
+        let (right, bottom) = (
+            (self.right() + by).min(bounds.right()),
+            (self.bottom() + by).min(bounds.bottom()),
+        );
+
+        Self::new(x, y, right - x, bottom - y)
+    }
+
+    /// Split this region into square tiles of the given size, in the given order. The tiles on
+    /// the right and bottom edges are smaller if the size doesn't divide the region evenly.
+    pub fn tiles(&self, tile_size: u32, order: TileOrder) -> Vec<Self> {
+        let tile_size = tile_size.max(1);
+        let columns = self.width.div_ceil(tile_size);
+        let rows = self.height.div_ceil(tile_size);
+
+        order
+            .grid_order(columns, rows)
+            .into_iter()
+            .map(|(column, row)| {
+                let (x, y) = (self.x + column * tile_size, self.y + row * tile_size);
+                Self::new(
+                    x,
+                    y,
+                    tile_size.min(self.right() - x),
+                    tile_size.min(self.bottom() - y),
+                )
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Region {
+    type Err = Report;
+
+    /// Parse a region in the form `x,y,width,height`.
+    fn from_str(s: &str) -> Result<Self> {
+        let numbers = s
+            .split(',')
+            .map(|number| {
+                number
+                    .trim()
+                    .parse::<u32>()
+                    .wrap_err_with(|| format!("When trying to parse {number:?} as a number"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let [x, y, width, height] = numbers[..] else {
+            return Err(eyre!(
+                "A region must be four numbers like x,y,width,height, not {s:?}"
+            ));
+        };
+        ensure!(
+            width > 0 && height > 0,
+            "A region must not be empty, but {s:?} is"
+        );
+        ensure!(
+            x.checked_add(width).is_some() && y.checked_add(height).is_some(),
+            "The region {s:?} goes past the largest possible image"
+        );
+
+        Ok(Self::new(x, y, width, height))
+    }
+}
+
+/// The order to render tiles in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Deserialize, Serialize)]
+pub enum TileOrder {
+    /// Left to right along each row, from the top row to the bottom.
+    Scanline,
+
+    /// Spiralling outwards from the middle, so the most interesting part of the image usually
+    /// finishes first.
+    #[default]
+    Spiral,
+
+    /// Along a Hilbert curve, which keeps each tile close to the ones just before it.
+    Hilbert,
+}
+
+impl TileOrder {
+    /// Put the cells of a grid with the given number of columns and rows into this order.
+    fn grid_order(self, columns: u32, rows: u32) -> Vec<(u32, u32)> {
+        let cell_count = columns as usize * rows as usize;
+        let in_grid = |(column, row): (i64, i64)| {
+            (0..columns as i64).contains(&column) && (0..rows as i64).contains(&row)
+        };
+
+        match self {
+            Self::Scanline => (0..rows)
+                .flat_map(|row| (0..columns).map(move |column| (column, row)))
+                .collect(),
+            Self::Spiral => {
+                let mut cells = Vec::with_capacity(cell_count);
+                let (mut column, mut row) = ((columns as i64 - 1) / 2, (rows as i64 - 1) / 2);
+                let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+                // Walk 1 step right, 1 down, 2 left, 2 up, 3 right, and so on, keeping the cells
+                // which are actually in the grid
+                let mut leg = 0;
+                while cells.len() < cell_count {
+                    let (dx, dy) = directions[leg % 4];
+                    for _ in 0..leg / 2 + 1 {
+                        if in_grid((column, row)) {
+                            cells.push((column as u32, row as u32));
+                        }
+                        column += dx;
+                        row += dy;
+                    }
+                    leg += 1;
+                }
+
+                cells.truncate(cell_count);
+                cells
+            }
+            Self::Hilbert => {
+                let side = columns.max(rows).next_power_of_two() as u64;
+                (0..side * side)
+                    .map(|distance| hilbert_point(side, distance))
+                    .filter(|&(column, row)| in_grid((column as i64, row as i64)))
+                    .map(|(column, row)| (column as u32, row as u32))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Get the point at the given distance along a Hilbert curve which fills a square with the given
+/// side length, which must be a power of two.
+fn hilbert_point(side: u64, distance: u64) -> (u64, u64) {
+    let (mut x, mut y) = (0, 0);
+    let mut t = distance;
+    let mut s = 1;
+
+    while s < side {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+
+        // Rotate the quadrant so that the curve joins up
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            (x, y) = (y, x);
+        }
+
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ValueEnum;
+
+    #[test]
+    fn regions_can_be_parsed() {
+        assert_eq!(
+            "10,20,300,400".parse::<Region>().unwrap(),
+            Region::new(10, 20, 300, 400)
+        );
+        assert_eq!(
+            " 0, 1 ,2,3 ".parse::<Region>().unwrap(),
+            Region::new(0, 1, 2, 3)
+        );
+    }
+
+    #[test]
+    fn malformed_regions_are_rejected() {
+        for s in [
+            "",
+            "1,2,3",
+            "1,2,3,4,5",
+            "1,2,,4",
+            "a,b,c,d",
+            "-1,0,4,4",
+            "1.5,0,4,4",
+            "1 2 3 4",
+            "0,0,0,4",
+            "0,0,4,0",
+            "4294967295,0,1,1",
+            "0,4294967295,1,1",
+            "1,0,4294967295,1",
+        ] {
+            assert!(s.parse::<Region>().is_err(), "{s:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn intersections_only_contain_pixels_in_both_regions() {
+        let region = Region::new(10, 10, 20, 10);
+        assert_eq!(
+            region.intersection(&Region::new(0, 15, 15, 100)),
+            Some(Region::new(10, 15, 5, 5))
+        );
+        assert_eq!(region.intersection(&Region::full(100, 100)), Some(region));
+        assert_eq!(
+            region.intersection(&Region::new(12, 12, 2, 2)),
+            Some(Region::new(12, 12, 2, 2))
+        );
+        // Regions which only touch at an edge don't share any pixels.
+        assert_eq!(region.intersection(&Region::new(30, 10, 5, 5)), None);
+        assert_eq!(region.intersection(&Region::new(0, 0, 5, 5)), None);
+    }
+
+    #[test]
+    fn expanding_a_region_stays_within_the_bounds() {
+        let bounds = Region::full(100, 50);
+        assert_eq!(
+            Region::new(10, 10, 20, 20).expand(3, &bounds),
+            Region::new(7, 7, 26, 26)
+        );
+        assert_eq!(
+            Region::new(2, 1, 10, 10).expand(5, &bounds),
+            Region::new(0, 0, 17, 16)
+        );
+        assert_eq!(
+            Region::new(90, 45, 10, 5).expand(5, &bounds),
+            Region::new(85, 40, 15, 10)
+        );
+        assert_eq!(
+            Region::new(10, 10, 20, 20).expand(0, &bounds),
+            Region::new(10, 10, 20, 20)
+        );
+        assert_eq!(
+            Region::new(10, 10, 2, 2).expand(5, &Region::new(8, 9, 10, 10)),
+            Region::new(8, 9, 9, 8)
+        );
+    }
+
+    #[test]
+    fn tiles_cover_every_pixel_exactly_once() {
+        let region = Region::new(3, 5, 37, 23);
+        for &order in TileOrder::value_variants() {
+            for tile_size in [0, 1, 4, 8, 16, 23, 64] {
+                let mut covered = vec![0; region.pixel_count()];
+                for tile in region.tiles(tile_size, order) {
+                    assert_eq!(
+                        tile.intersection(&region),
+                        Some(tile),
+                        "{tile:?} isn't in the region with {order:?} order and size {tile_size}"
+                    );
+                    for y in tile.y..tile.bottom() {
+                        for x in tile.x..tile.right() {
+                            covered[region.index(x, y)] += 1;
+                        }
+                    }
+                }
+
+                assert!(
+                    covered.iter().all(|&count| count == 1),
+                    "{order:?} order with size {tile_size} doesn't cover each pixel once"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! This module handles converting linear radiance into colours that can be displayed.
+
+use crate::vector::{v, Colour};
+use image::{Rgb32FImage, RgbImage};
+use rayon::iter::{IndexedParallelIterator, ParallelIterator};
+use rayon::slice::{ParallelSlice, ParallelSliceMut};
+
+/// A curve which maps linear radiance in `[0, infinity)` onto display values in `[0, 1]`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ToneMap {
+    /// Just clip the values to `[0, 1]`, without any gamma correction.
+    Linear,
+
+    /// Clip the values to `[0, 1]` and encode them with the sRGB transfer function.
+    #[default]
+    Srgb,
+
+    /// Compress highlights with the Reinhard operator, `x / (1 + x)`, then encode with sRGB.
+    Reinhard,
+
+    /// Use Krzysztof Narkowicz's fit of the ACES filmic curve, then encode with sRGB.
+    Aces,
+}
+
+/// A view transform, which turns the linear float image into something that looks right on a
+/// normal display.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ViewTransform {
+    /// The tone mapping curve.
+    pub tone_map: ToneMap,
+
+    /// The exposure adjustment in stops. Each stop doubles the brightness.
+    pub exposure: f64,
+}
+
+impl Default for ViewTransform {
+    fn default() -> Self {
+        Self {
+            tone_map: ToneMap::default(),
+            exposure: 0.,
+        }
+    }
+}
+
+impl ViewTransform {
+    /// Apply this view transform to a linear colour, returning a display colour in `[0, 1]`.
+    #[inline]
+    pub fn apply(&self, colour: Colour) -> Colour {
+        let colour = colour.map(|x| x.max(0.)) * self.exposure.exp2();
+
+        match self.tone_map {
+            ToneMap::Linear => colour.map(|x| x.min(1.)),
+            ToneMap::Srgb => colour.map(|x| srgb_encode(x.min(1.))),
+            ToneMap::Reinhard => colour.map(|x| srgb_encode(x / (1. + x))),
+            ToneMap::Aces => colour.map(|x| srgb_encode(aces_filmic(x))),
+        }
+    }
+
+    /// Apply this view transform to a linear colour and quantise the result to 8 bits per
+    /// channel.
+    #[inline]
+    pub fn apply_u8(&self, colour: Colour) -> [u8; 3] {
+        let colour = self.apply(colour);
+        [colour.x, colour.y, colour.z].map(|x| (x * 255.).round() as u8)
+    }
+
+    /// Apply this view transform to a whole linear float image.
+    pub fn apply_to_image(&self, float_img: &Rgb32FImage) -> RgbImage {
+        let mut img = RgbImage::new(float_img.width(), float_img.height());
+
+        img.par_chunks_exact_mut(3)
+            .zip(float_img.par_chunks_exact(3))
+            .for_each(|(out, pixel)| {
+                out.copy_from_slice(&self.apply_u8(v!(pixel[0], pixel[1], pixel[2])));
+            });
+
+        img
+    }
+}
+
+/// The sRGB transfer function, which encodes a linear value in `[0, 1]` for display.
+#[inline]
+pub fn srgb_encode(x: f64) -> f64 {
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// The inverse of the sRGB transfer function, which decodes a display value in `[0, 1]` into
+/// linear space.
+#[inline]
+pub fn srgb_decode(x: f64) -> f64 {
+    if x <= 0.040_45 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Krzysztof Narkowicz's curve fit of the ACES filmic tone mapping curve. See
+/// <https://knarkowicz.wordpress.com/2016/01/06/aces-filmic-tone-mapping-curve/>.
+#[inline]
+fn aces_filmic(x: f64) -> f64 {
+    // The fit expects the input to be pre-exposed by 0.6 to match the reference curve
+    let x = 0.6 * x;
+    let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+    ((x * (a * x + b)) / (x * (c * x + d) + e)).clamp(0., 1.)
+}
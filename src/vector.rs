@@ -1,13 +1,18 @@
 //! This module handles vectors.
 
+use color_eyre::{
+    eyre::{eyre, Context},
+    Report, Result,
+};
 use core::{
+    f64::consts::PI,
+    fmt,
     iter::Sum,
     ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign},
+    str::FromStr,
 };
-use rand::{
-    distributions::{Distribution, Uniform},
-    thread_rng,
-};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 /// An RGB colour.
 pub type Colour = Vec3;
@@ -16,7 +21,10 @@ pub type Colour = Vec3;
 pub type Point = Vec3;
 
 /// A vector of three floats.
-#[derive(Clone, Copy, Debug, PartialEq)]
+///
+/// This gets (de)serialized as an array of three numbers.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(from = "[f64; 3]", into = "[f64; 3]")]
 pub struct Vec3 {
     pub x: f64,
     pub y: f64,
@@ -71,6 +79,53 @@ impl Vec3 {
         }
     }
 
+    /// Take the elementwise minimum of this vector and another.
+    #[inline]
+    pub fn min_elementwise(self, other: Self) -> Self {
+        Self {
+            x: self.x.min(other.x),
+            y: self.y.min(other.y),
+            z: self.z.min(other.z),
+        }
+    }
+
+    /// Take the elementwise maximum of this vector and another.
+    #[inline]
+    pub fn max_elementwise(self, other: Self) -> Self {
+        Self {
+            x: self.x.max(other.x),
+            y: self.y.max(other.y),
+            z: self.z.max(other.z),
+        }
+    }
+
+    /// Get the component of this vector along the given axis, where 0 is x, 1 is y, and 2 is z.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the axis is not 0, 1, or 2.
+    #[inline]
+    pub fn axis(self, axis: usize) -> f64 {
+        match axis {
+            0 => self.x,
+            1 => self.y,
+            2 => self.z,
+            _ => panic!("Axis must be 0, 1, or 2, not {axis}"),
+        }
+    }
+
+    /// Get the largest component of this vector.
+    #[inline]
+    pub fn max_component(self) -> f64 {
+        self.x.max(self.y).max(self.z)
+    }
+
+    /// Get the relative luminance of this vector as a linear colour.
+    #[inline]
+    pub fn luminance(self) -> f64 {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
+    }
+
     /// Get the length (magnitude) of this vector.
     #[inline]
     pub fn len(self) -> f64 {
@@ -129,17 +184,38 @@ impl Vec3 {
         self.map(|x| (x + 1.) / 2.)
     }
 
-    /// Generate a random unit vector.
-    pub fn random_unit_vector() -> Self {
-        let distribution = Uniform::new_inclusive(-1., 1.);
-        let mut rng = thread_rng();
+    /// Generate a random unit vector, uniformly distributed over the sphere.
+    pub fn random_unit_vector(rng: &mut (impl Rng + ?Sized)) -> Self {
+        Self::unit_vector_from_sample((rng.gen(), rng.gen()))
+    }
+
+    /// Map a point in the unit square to a unit vector, such that uniformly distributed points
+    /// give uniformly distributed unit vectors.
+    pub fn unit_vector_from_sample((u, v): (f64, f64)) -> Self {
+        let z = 1. - 2. * u;
+        let phi = 2. * PI * v;
+        let r = (1. - z * z).max(0.).sqrt();
 
         Self {
-            x: distribution.sample(&mut rng),
-            y: distribution.sample(&mut rng),
-            z: distribution.sample(&mut rng),
+            x: r * phi.cos(),
+            y: r * phi.sin(),
+            z,
         }
-        .normalise()
+    }
+
+    /// Assuming this is a unit vector, get two more unit vectors which form an orthonormal basis
+    /// with it.
+    ///
+    /// This uses the method from "Building an Orthonormal Basis, Revisited" by Duff et al.
+    pub fn orthonormal_basis(self) -> (Self, Self) {
+        let sign = 1_f64.copysign(self.z);
+        let a = -1. / (sign + self.z);
+        let b = self.x * self.y * a;
+
+        (
+            Self::new(1. + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            Self::new(b, sign + self.y * self.y * a, -self.y),
+        )
     }
 
     /// Is this vector equal to zero (within a tolerance of `1e-10`)?
@@ -149,6 +225,50 @@ impl Vec3 {
     }
 }
 
+impl From<[f64; 3]> for Vec3 {
+    fn from([x, y, z]: [f64; 3]) -> Self {
+        Self { x, y, z }
+    }
+}
+
+impl From<Vec3> for [f64; 3] {
+    fn from(value: Vec3) -> Self {
+        [value.x, value.y, value.z]
+    }
+}
+
+impl FromStr for Vec3 {
+    type Err = Report;
+
+    /// Parse a vector in the form `x,y,z`.
+    fn from_str(s: &str) -> Result<Self> {
+        let components = s
+            .split(',')
+            .map(|component| {
+                component
+                    .trim()
+                    .parse::<f64>()
+                    .wrap_err_with(|| format!("When trying to parse {component:?} as a number"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let [x, y, z] = components[..] else {
+            return Err(eyre!(
+                "A vector must be three numbers like x,y,z, not {s:?}"
+            ));
+        };
+
+        Ok(Self { x, y, z })
+    }
+}
+
+impl fmt::Display for Vec3 {
+    /// Write the vector in the form `x,y,z`, which can be parsed back again.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{}", self.x, self.y, self.z)
+    }
+}
+
 impl From<Vec3> for [u8; 3] {
     fn from(value: Vec3) -> Self {
         value.into_u8_array()